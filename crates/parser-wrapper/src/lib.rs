@@ -1,13 +1,80 @@
-use parser::parse;
+use std::collections::HashMap;
+use std::path::Path;
+
+use parser::{filter_by_tags, parse_with_env};
 use wasm_bindgen::prelude::*;
 
+#[wasm_bindgen(module = "fs")]
+extern "C" {
+    #[wasm_bindgen(catch, js_name = readFileSync)]
+    fn read_file_sync(path: &str, encoding: &str) -> Result<String, JsValue>;
+}
+
+/// Parse the test specification, `vars` is an optional json object with the
+/// variables to use in the test specification (e.g. `{"PARA": 2000}`) and
+/// `file_path` the optional path of the test specification, used to resolve
+/// the included files. `include_tags` and `exclude_tags` are optional comma
+/// separated lists of tags used to select the assertions to run. `env` is an
+/// optional json object with the environment variables (e.g. `process.env`),
+/// since the process environment isn't available in wasm.
 #[wasm_bindgen]
-pub fn parse_to_json(unparsed_file: &str) -> Result<String, String> {
+pub fn parse_to_json(
+    unparsed_file: &str,
+    vars: Option<String>,
+    file_path: Option<String>,
+    include_tags: Option<String>,
+    exclude_tags: Option<String>,
+    env: Option<String>,
+) -> Result<String, String> {
     if unparsed_file.is_empty() {
         return Err("Provided test specification is empty".to_string());
     }
-    let ast = parse(unparsed_file).map_err(|e| e.to_string())?;
+    let vars = parse_vars(vars.as_deref())?;
+    let env = parse_vars(env.as_deref())?;
+    let file_path = file_path.unwrap_or_default();
+    let ast = parse_with_env(
+        unparsed_file,
+        Path::new(&file_path),
+        &vars,
+        &|path| {
+            read_file_sync(&path.to_string_lossy(), "utf8")
+                .map_err(|e| e.as_string().unwrap_or_else(|| format!("{e:?}")))
+        },
+        &|name| env.get(name).cloned(),
+    )
+    .map_err(|e| e.to_string())?;
+    let ast = filter_by_tags(
+        ast,
+        &parse_tags(include_tags.as_deref()),
+        &parse_tags(exclude_tags.as_deref()),
+    );
     let ast_json =
         serde_json::to_string_pretty(&ast).map_err(|_| "Serializing error".to_string())?;
     Ok(ast_json)
 }
+
+fn parse_vars(vars: Option<&str>) -> Result<HashMap<String, String>, String> {
+    let vars = match vars {
+        Some(vars) => vars,
+        None => return Ok(HashMap::new()),
+    };
+
+    let values: HashMap<String, serde_json::Value> =
+        serde_json::from_str(vars).map_err(|e| format!("Invalid variables: {e}"))?;
+    Ok(values
+        .into_iter()
+        .map(|(key, value)| match value {
+            serde_json::Value::String(s) => (key, s),
+            other => (key, other.to_string()),
+        })
+        .collect())
+}
+
+fn parse_tags(tags: Option<&str>) -> Vec<String> {
+    tags.unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|tag| !tag.is_empty())
+        .map(str::to_string)
+        .collect()
+}
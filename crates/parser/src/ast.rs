@@ -10,117 +10,414 @@ pub enum Operator {
     IsAtLeast,
     IsBelow,
     IsAtMost,
+    /// Inside the range, both bounds included.
+    IsBetween,
+    /// Inside the range, upper bound excluded.
+    IsWithinRange,
 }
 
 #[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Copy, Clone)]
 pub enum MathOps {
     Minus,
     Plus,
+    Multiply,
+    Divide,
+}
+
+/// Arithmetic expression of metrics, evaluated with the usual precedence
+/// (operators of the same precedence are left associative).
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub enum MetricExpr {
+    Metric(MetricSelector),
+    Constant(NumericValue),
+    BinaryOp {
+        op: MathOps,
+        lhs: Box<MetricExpr>,
+        rhs: Box<MetricExpr>,
+    },
+}
+
+/// Operands of a `calc_metrics` expression of two metrics (e.g. `block height - finalised block`).
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub struct CalcMetricsOperands {
+    pub metric_name_a: MetricSelector,
+    pub math_ops: MathOps,
+    pub metric_name_b: MetricSelector,
+}
+
+/// Operator of a prometheus label matcher.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Copy, Clone)]
+pub enum LabelOp {
+    /// `=`
+    Equal,
+    /// `!=`
+    NotEqual,
+    /// `=~`
+    RegexMatch,
+    /// `!~`
+    RegexNotMatch,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub struct LabelMatcher {
+    pub label: String,
+    pub op: LabelOp,
+    pub value: String,
+}
+
+/// Metric to query, with the label matchers to select the series
+/// (e.g. `dispute_votes{validity="valid",origin!="local"}`).
+///
+/// A metric without matchers is serialized as a plain string, so existing consumers
+/// keep working, like a single node of `NodeSelector`.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+#[serde(from = "MetricSelectorRepr", into = "MetricSelectorRepr")]
+pub struct MetricSelector {
+    pub name: String,
+    pub matchers: Vec<LabelMatcher>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum MetricSelectorRepr {
+    Name(String),
+    Selector {
+        name: String,
+        #[serde(default)]
+        matchers: Vec<LabelMatcher>,
+    },
+}
+
+impl From<MetricSelectorRepr> for MetricSelector {
+    fn from(repr: MetricSelectorRepr) -> Self {
+        match repr {
+            MetricSelectorRepr::Name(name) => MetricSelector {
+                name,
+                matchers: vec![],
+            },
+            MetricSelectorRepr::Selector { name, matchers } => MetricSelector { name, matchers },
+        }
+    }
+}
+
+impl From<MetricSelector> for MetricSelectorRepr {
+    fn from(selector: MetricSelector) -> Self {
+        if selector.matchers.is_empty() {
+            MetricSelectorRepr::Name(selector.name)
+        } else {
+            MetricSelectorRepr::Selector {
+                name: selector.name,
+                matchers: selector.matchers,
+            }
+        }
+    }
+}
+
+/// Function applied to a metric over a time window.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Copy, Clone)]
+pub enum WindowFn {
+    /// Per-second average increase
+    Rate,
+    /// Total increase
+    Increase,
+}
+
+/// Numeric value used as target of a comparison.
+///
+/// Integers are kept as such and floats cover decimals and scientific notation,
+/// both are serialized as plain json numbers.
+#[derive(Debug, Serialize, Deserialize, Copy, Clone)]
+#[serde(untagged)]
+pub enum NumericValue {
+    Integer(i64),
+    Float(f64),
+}
+
+impl PartialEq for NumericValue {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (NumericValue::Integer(a), NumericValue::Integer(b)) => a == b,
+            // compare the bits to keep `Eq` sound
+            (NumericValue::Float(a), NumericValue::Float(b)) => a.to_bits() == b.to_bits(),
+            _ => false,
+        }
+    }
+}
+
+impl Eq for NumericValue {}
+
+impl NumericValue {
+    pub fn as_f64(&self) -> f64 {
+        match self {
+            NumericValue::Integer(v) => *v as f64,
+            NumericValue::Float(v) => *v,
+        }
+    }
+}
+
+/// Target of a comparison, a single value or a `[start, end]` range
+/// (used with the `IsBetween` and `IsWithinRange` operators).
+///
+/// Booleans and strings are only compared with `Equal` and `NotEqual`.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+#[serde(untagged)]
+pub enum TargetValue {
+    Single(NumericValue),
+    Range(NumericValue, NumericValue),
+    Bool(bool),
+    Text(String),
 }
 
 #[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
 pub struct Comparison {
     pub op: Operator,
-    pub target_value: u64,
+    pub target_value: TargetValue,
+}
+
+/// Typed argument of an extrinsic call.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub enum ArgValue {
+    /// Dev account or derivation path (e.g. `//Alice`), resolved by the runner
+    Account(String),
+    Number(NumericValue),
+    Bool(bool),
+    /// Hex encoded bytes, with the `0x` prefix
+    Hex(String),
+    Text(String),
+    List(Vec<ArgValue>),
+}
+
+/// Segment of the path to a field of a (json) value, a field name or a list index.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+#[serde(untagged)]
+pub enum PathSegment {
+    Field(String),
+    Index(u32),
+}
+
+/// New artifact of an upgraded node.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub enum UpgradeArtifact {
+    /// Path of the binary
+    Binary(String),
+    /// Container image reference
+    Image(String),
+}
+
+/// Value captured from a log line by the runner.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub struct Capture {
+    /// Named group of the regex
+    pub group: String,
+    /// Variable to store the value in
+    pub var: String,
+}
+
+/// Block status to wait for after submitting an extrinsic.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Copy, Clone)]
+pub enum WaitFor {
+    Inclusion,
+    Finalization,
 }
 
 pub type ParaId = u16;
 pub type NodeName = String;
 
+/// Node(s) targeted by an assertion or command.
+///
+/// A single node is serialized as a plain string, so existing consumers keep working,
+/// while the other selectors are serialized as objects the runner can fan out.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+#[serde(untagged)]
+pub enum NodeSelector {
+    /// `alice:`
+    Name(NodeName),
+    /// `alice,bob,charlie:`
+    List { nodes: Vec<NodeName> },
+    /// `validator-*:`
+    Glob { glob: String },
+    /// `all validators:`
+    Group { group: String },
+}
+
 #[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
 #[serde(tag = "fn", content = "args")]
 pub enum AssertionKind {
     IsUp {
-        node_name: NodeName,
+        node_name: NodeSelector,
         #[serde(with = "optional_timeout")]
         timeout: Option<Duration>,
     },
     ParaIsRegistered {
-        node_name: NodeName,
+        node_name: NodeSelector,
         para_id: ParaId,
+        /// Assert that the parachain is not (or stays not) registered
+        #[serde(default)]
+        negated: bool,
         #[serde(with = "optional_timeout")]
         timeout: Option<Duration>,
     },
     ParaBlockHeight {
-        node_name: NodeName,
+        node_name: NodeSelector,
+        para_id: ParaId,
+        op: Operator,
+        target_value: TargetValue,
+        #[serde(with = "optional_timeout")]
+        timeout: Option<Duration>,
+    },
+    ParaFinalizedHeight {
+        node_name: NodeSelector,
+        para_id: ParaId,
+        op: Operator,
+        target_value: TargetValue,
+        #[serde(with = "optional_timeout")]
+        timeout: Option<Duration>,
+    },
+    /// Compare the number of blocks produced by the parachain since the start
+    /// of the assertion (until the `timeout`), instead of the absolute height
+    ParaBlockProduction {
+        node_name: NodeSelector,
         para_id: ParaId,
         op: Operator,
-        target_value: u64,
+        target_value: TargetValue,
         #[serde(with = "optional_timeout")]
         timeout: Option<Duration>,
     },
     CalcMetrics {
-        node_name: NodeName,
-        metric_name_a: String,
-        math_ops: MathOps,
-        metric_name_b: String,
+        node_name: NodeSelector,
+        expression: MetricExpr,
+        /// Operands of the expression when it's `metric_a (+|-) metric_b`,
+        /// kept for the consumers of the previous format
+        #[serde(flatten)]
+        operands: Option<CalcMetricsOperands>,
         op: Operator,
-        target_value: u64,
+        target_value: TargetValue,
         #[serde(with = "optional_timeout")]
         timeout: Option<Duration>,
     },
     ParaRuntimeUpgrade {
-        node_name: NodeName,
+        node_name: NodeSelector,
         para_id: ParaId,
         file_or_uri: String,
+        /// `spec_version` of the runtime once the upgrade is applied
+        #[serde(default)]
+        expected_spec_version: Option<u32>,
+        #[serde(with = "optional_timeout")]
+        timeout: Option<Duration>,
+    },
+    ParaRuntimeSpecVersion {
+        node_name: NodeSelector,
+        para_id: ParaId,
+        op: Operator,
+        target_value: TargetValue,
         #[serde(with = "optional_timeout")]
         timeout: Option<Duration>,
     },
     ParaRuntimeDummyUpgrade {
-        node_name: NodeName,
+        node_name: NodeSelector,
         para_id: ParaId,
         #[serde(with = "optional_timeout")]
         timeout: Option<Duration>,
     },
+    /// Compare the result of a json-rpc call, or the field at `path` of it
+    Rpc {
+        node_name: NodeSelector,
+        method: String,
+        #[serde(default)]
+        params: Vec<serde_json::Value>,
+        path: Vec<PathSegment>,
+        op: Operator,
+        target_value: TargetValue,
+        #[serde(with = "optional_timeout")]
+        timeout: Option<Duration>,
+    },
+    /// Compare the value of a storage item, or of the field at `path` of it
+    StorageQuery {
+        node_name: NodeSelector,
+        pallet: String,
+        item: String,
+        keys: Vec<ArgValue>,
+        path: Vec<PathSegment>,
+        op: Operator,
+        target_value: TargetValue,
+        #[serde(with = "optional_timeout")]
+        timeout: Option<Duration>,
+    },
     Histogram {
-        node_name: NodeName,
-        metric_name: String,
+        node_name: NodeSelector,
+        metric_name: MetricSelector,
         op: Operator,
-        target_value: u64,
+        target_value: TargetValue,
         buckets: Vec<String>,
         #[serde(with = "optional_timeout")]
         timeout: Option<Duration>,
     },
+    /// Compare the quantile estimated from the buckets of the histogram
+    /// (see [`crate::metrics::histogram_quantile`])
+    HistogramQuantile {
+        node_name: NodeSelector,
+        metric_name: MetricSelector,
+        /// Quantile to estimate, between 0 and 1
+        quantile: NumericValue,
+        op: Operator,
+        target_value: TargetValue,
+        #[serde(with = "optional_timeout")]
+        timeout: Option<Duration>,
+    },
     Report {
-        node_name: NodeName,
-        metric_name: String,
+        node_name: NodeSelector,
+        metric_name: MetricSelector,
+        /// Function to compute from two samples of the metric, `window` apart
+        #[serde(default)]
+        window_fn: Option<WindowFn>,
+        #[serde(default, with = "optional_timeout")]
+        window: Option<Duration>,
         op: Operator,
-        target_value: u64,
+        target_value: TargetValue,
         #[serde(with = "optional_timeout")]
         timeout: Option<Duration>,
     },
     LogMatch {
-        node_name: NodeName,
+        node_name: NodeSelector,
+        /// Assert that the pattern doesn't match, within the `timeout` window
+        #[serde(default)]
+        negated: bool,
         match_type: String,
         pattern: String,
+        /// Named group of the pattern to save, and reuse as `${var}` in the next lines
+        #[serde(default)]
+        capture: Option<Capture>,
         #[serde(with = "optional_timeout")]
         timeout: Option<Duration>,
     },
     CountLogMatch {
-        node_name: NodeName,
+        node_name: NodeSelector,
         match_type: String,
         pattern: String,
         op: Operator,
-        target_value: u64,
+        target_value: TargetValue,
         #[serde(with = "optional_timeout")]
         timeout: Option<Duration>,
     },
     Trace {
-        node_name: NodeName,
+        node_name: NodeSelector,
         span_id: String,
         pattern: String,
         #[serde(with = "optional_timeout")]
         timeout: Option<Duration>,
     },
     SystemEvent {
-        node_name: NodeName,
+        node_name: NodeSelector,
+        /// Assert that the pattern doesn't match, within the `timeout` window
+        #[serde(default)]
+        negated: bool,
         match_type: String,
         pattern: String,
         #[serde(with = "optional_timeout")]
         timeout: Option<Duration>,
     },
     CustomJs {
-        node_name: NodeName,
+        node_name: NodeSelector,
         file_path: PathBuf,
         custom_args: Option<String>,
         #[serde(flatten)]
@@ -130,7 +427,7 @@ pub enum AssertionKind {
         is_ts: bool,
     },
     CustomSh {
-        node_name: NodeName,
+        node_name: NodeSelector,
         file_path: PathBuf,
         custom_args: Option<String>,
         #[serde(flatten)]
@@ -138,14 +435,23 @@ pub enum AssertionKind {
         #[serde(with = "optional_timeout")]
         timeout: Option<Duration>,
     },
+    /// Check a value published in the backchannel, when `cmp` is not set
+    /// only check that the key exists
+    Backchannel {
+        key: String,
+        #[serde(flatten)]
+        cmp: Option<Comparison>,
+        #[serde(with = "optional_timeout")]
+        timeout: Option<Duration>,
+    },
     Pause {
-        node_name: NodeName,
+        node_name: NodeSelector,
     },
     Resume {
-        node_name: NodeName,
+        node_name: NodeSelector,
     },
     Restart {
-        node_name: NodeName,
+        node_name: NodeSelector,
         #[serde(with = "optional_timeout")]
         after: Option<Duration>,
     },
@@ -153,12 +459,187 @@ pub enum AssertionKind {
         #[serde(with = "optional_timeout")]
         seconds: Option<Duration>,
     },
+    /// Drop the connections between the node and the `peers`
+    Disconnect {
+        node_name: NodeSelector,
+        peers: NodeSelector,
+        /// When not set the chaos lasts until the end of the test
+        #[serde(with = "optional_timeout")]
+        duration: Option<Duration>,
+    },
+    /// Drop all the connections of the node
+    Isolate {
+        node_name: NodeSelector,
+        #[serde(with = "optional_timeout")]
+        duration: Option<Duration>,
+    },
+    AddLatency {
+        node_name: NodeSelector,
+        #[serde(with = "duration")]
+        latency: Duration,
+        #[serde(with = "optional_timeout")]
+        duration: Option<Duration>,
+    },
+    PacketLoss {
+        node_name: NodeSelector,
+        /// Percentage of packets to drop, between 0 and 100
+        loss_percent: NumericValue,
+        #[serde(with = "optional_timeout")]
+        duration: Option<Duration>,
+    },
+    /// Spawn a new node with the same settings as the `template` node
+    AddNode {
+        node_name: NodeName,
+        template: NodeName,
+        /// Extra command line arguments of the new node
+        #[serde(default)]
+        args: Vec<String>,
+    },
+    RemoveNode {
+        node_name: NodeName,
+    },
+    /// Restart the node with a new binary or image, keeping its database
+    UpgradeNode {
+        node_name: NodeSelector,
+        artifact: UpgradeArtifact,
+        /// Extra command line arguments of the upgraded node
+        #[serde(default)]
+        args: Vec<String>,
+        #[serde(with = "optional_timeout")]
+        after: Option<Duration>,
+    },
+    SubmitExtrinsic {
+        node_name: NodeSelector,
+        pallet: String,
+        call: String,
+        args: Vec<ArgValue>,
+        /// Account uri of the signer (e.g. `//Alice`)
+        signer: String,
+        /// When not set the command returns once the extrinsic is submitted
+        #[serde(default)]
+        wait_for: Option<WaitFor>,
+        #[serde(with = "optional_timeout")]
+        timeout: Option<Duration>,
+    },
+}
+
+impl AssertionKind {
+    /// Commands change the state of the network, so they act as ordering barriers.
+    pub fn is_command(&self) -> bool {
+        matches!(
+            self,
+            AssertionKind::Pause { .. }
+                | AssertionKind::Resume { .. }
+                | AssertionKind::Restart { .. }
+                | AssertionKind::Sleep { .. }
+                | AssertionKind::Disconnect { .. }
+                | AssertionKind::Isolate { .. }
+                | AssertionKind::AddLatency { .. }
+                | AssertionKind::PacketLoss { .. }
+                | AssertionKind::AddNode { .. }
+                | AssertionKind::RemoveNode { .. }
+                | AssertionKind::UpgradeNode { .. }
+                | AssertionKind::SubmitExtrinsic { .. }
+        )
+    }
+
+    /// Nodes the assertion or command runs on or refers to (e.g. the peers to disconnect).
+    pub fn node_selectors(&self) -> Vec<&NodeSelector> {
+        match self {
+            AssertionKind::Disconnect {
+                node_name, peers, ..
+            } => vec![node_name, peers],
+            AssertionKind::IsUp { node_name, .. }
+            | AssertionKind::ParaIsRegistered { node_name, .. }
+            | AssertionKind::ParaBlockHeight { node_name, .. }
+            | AssertionKind::ParaFinalizedHeight { node_name, .. }
+            | AssertionKind::ParaBlockProduction { node_name, .. }
+            | AssertionKind::CalcMetrics { node_name, .. }
+            | AssertionKind::ParaRuntimeUpgrade { node_name, .. }
+            | AssertionKind::ParaRuntimeSpecVersion { node_name, .. }
+            | AssertionKind::ParaRuntimeDummyUpgrade { node_name, .. }
+            | AssertionKind::Rpc { node_name, .. }
+            | AssertionKind::StorageQuery { node_name, .. }
+            | AssertionKind::Histogram { node_name, .. }
+            | AssertionKind::HistogramQuantile { node_name, .. }
+            | AssertionKind::Report { node_name, .. }
+            | AssertionKind::LogMatch { node_name, .. }
+            | AssertionKind::CountLogMatch { node_name, .. }
+            | AssertionKind::Trace { node_name, .. }
+            | AssertionKind::SystemEvent { node_name, .. }
+            | AssertionKind::CustomJs { node_name, .. }
+            | AssertionKind::CustomSh { node_name, .. }
+            | AssertionKind::Pause { node_name }
+            | AssertionKind::Resume { node_name }
+            | AssertionKind::Restart { node_name, .. }
+            | AssertionKind::Isolate { node_name, .. }
+            | AssertionKind::AddLatency { node_name, .. }
+            | AssertionKind::PacketLoss { node_name, .. }
+            | AssertionKind::UpgradeNode { node_name, .. }
+            | AssertionKind::SubmitExtrinsic { node_name, .. } => vec![node_name],
+            // names of added/removed nodes are checked by the parser
+            AssertionKind::Sleep { .. }
+            | AssertionKind::Backchannel { .. }
+            | AssertionKind::AddNode { .. }
+            | AssertionKind::RemoveNode { .. } => vec![],
+        }
+    }
+}
+
+/// Runner polling settings of an assertion, when not set the runner defaults are used.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub struct TimingPolicy {
+    /// Number of retries after a failed attempt
+    pub retries: Option<u32>,
+    /// Interval between attempts
+    #[serde(with = "optional_timeout")]
+    pub interval: Option<Duration>,
 }
 
 #[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
 pub struct Assertion {
     pub original_line: String,
     pub parsed: AssertionKind,
+    #[serde(default)]
+    pub timing: Option<TimingPolicy>,
+    /// Name of the `Test:` heading the assertion belongs to
+    #[serde(default)]
+    pub test: Option<String>,
+    /// Name of the `Section:` heading the assertion belongs to
+    #[serde(default)]
+    pub section: Option<String>,
+    /// Tags of the assertion, including the ones of its headings and blocks
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+/// A step of the test, steps are executed in order.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(untagged)]
+#[allow(clippy::large_enum_variant)]
+pub enum Step {
+    Assertion(Assertion),
+    /// Steps executed concurrently (only assertions, commands are ordering barriers).
+    Parallel {
+        parallel: Vec<Step>,
+    },
+    Section(Section),
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Copy, Clone)]
+pub enum SectionKind {
+    Test,
+    Section,
+}
+
+/// Steps grouped under a `Test:` or `Section:` heading, a test can contain sections.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Section {
+    pub kind: SectionKind,
+    pub name: String,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    pub steps: Vec<Step>,
 }
 
 #[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
@@ -166,7 +647,7 @@ pub struct TestDefinition {
     pub description: Option<String>,
     pub network: String,
     pub creds: Option<String>,
-    pub assertions: Vec<Assertion>,
+    pub assertions: Vec<Step>,
 }
 
 pub mod optional_timeout {
@@ -175,27 +656,65 @@ pub mod optional_timeout {
 
     use serde::{self, Deserialize, Deserializer, Serializer};
 
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Seconds {
+        Whole(u64),
+        Fractional(f64),
+    }
+
+    /// Serialize as whole seconds, or as fractional seconds if there are sub-second units
     pub fn serialize<S>(timeout: &Option<Duration>, serializer: S) -> Result<S::Ok, S::Error>
     where
         S: Serializer,
     {
         match timeout {
-            Some(secs) => serializer.serialize_some(&secs.as_secs()),
+            Some(duration) if duration.subsec_nanos() == 0 => {
+                serializer.serialize_some(&duration.as_secs())
+            }
+            Some(duration) => serializer.serialize_some(&duration.as_secs_f64()),
             None => serializer.serialize_none(),
         }
     }
 
-    /// Attempts to deserialize an u64 as Option<Duration>
+    /// Attempts to deserialize an u64 or f64 (seconds) as Option<Duration>
     pub fn deserialize<'de, D>(deserializer: D) -> Result<Option<Duration>, D::Error>
     where
         D: Deserializer<'de>,
     {
-        match u64::deserialize(deserializer) {
-            Ok(s) => Ok(Some(Duration::from_secs(s))),
+        match Seconds::deserialize(deserializer) {
+            Ok(Seconds::Whole(s)) => Ok(Some(Duration::from_secs(s))),
+            // durations in the DSL have (at most) millisecond precision
+            Ok(Seconds::Fractional(s)) => {
+                Ok(Some(Duration::from_millis((s * 1000.0).round() as u64)))
+            }
             Err(_) => {
-                // If we can deserialize to an u64 deserialize to None
+                // If we can't deserialize to seconds deserialize to None
                 Ok(None)
             }
         }
     }
 }
+
+/// Same representation as [`optional_timeout`], for required durations.
+pub mod duration {
+
+    use std::time::Duration;
+
+    use serde::{de::Error, Deserializer, Serializer};
+
+    pub fn serialize<S>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        super::optional_timeout::serialize(&Some(*duration), serializer)
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Duration, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        super::optional_timeout::deserialize(deserializer)?
+            .ok_or_else(|| D::Error::custom("expected a duration in seconds"))
+    }
+}
@@ -1,6 +1,7 @@
 use fs_err as fs;
 
 use clap::Parser;
+use std::collections::HashMap;
 use std::path::PathBuf;
 
 #[derive(Parser)]
@@ -9,16 +10,35 @@ struct Cli {
     /// Optional name to operate on
     #[clap(value_parser)]
     file_path: PathBuf,
+    /// Variable to use in the test file, as `KEY=VALUE` (can be repeated)
+    #[clap(long = "var", value_parser = parse_key_val)]
+    vars: Vec<(String, String)>,
+    /// Only keep the assertions with this tag (can be repeated)
+    #[clap(long = "include-tag", value_parser)]
+    include_tags: Vec<String>,
+    /// Drop the assertions with this tag (can be repeated)
+    #[clap(long = "exclude-tag", value_parser)]
+    exclude_tags: Vec<String>,
+}
+
+fn parse_key_val(s: &str) -> Result<(String, String), String> {
+    s.split_once('=')
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .ok_or_else(|| format!("invalid KEY=VALUE: no `=` found in `{s}`"))
 }
 
 pub fn main() {
     let cli = Cli::parse();
     let unparsed_file = fs::read_to_string(&cli.file_path)
         .unwrap_or_else(|_| panic!("cannot read file {}", cli.file_path.to_string_lossy()));
+    let vars: HashMap<String, String> = cli.vars.into_iter().collect();
 
-    let a = parser::parse(&unparsed_file);
+    let a = parser::parse_with_loader(&unparsed_file, &cli.file_path, &vars, &|path| {
+        fs::read_to_string(path).map_err(|e| e.to_string())
+    });
     match a {
         Ok(test_def) => {
+            let test_def = parser::filter_by_tags(test_def, &cli.include_tags, &cli.exclude_tags);
             println!("{}", serde_json::to_string_pretty(&test_def).unwrap());
         }
         Err(e) => {
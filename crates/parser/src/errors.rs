@@ -13,4 +13,22 @@ pub enum ParserError {
     Unexpected(String),
     #[error("Unreachable rule: \n {0}")]
     UnreachableRule(String),
+    #[error("Undefined variable `{0}` at line {1}")]
+    UndefinedVariable(String, usize),
+    #[error("Include cycle detected: {0}")]
+    IncludeCycle(String),
+    #[error("Can't load included file {0}: {1}")]
+    IncludeLoad(String, String),
+    #[error("{1}\n in included file: {0}")]
+    Include(String, Box<ParserError>),
+    #[error("Invalid parallel block: {0}")]
+    InvalidParallelBlock(String),
+    #[error("Invalid regex `{0}`: {1}")]
+    InvalidRegex(String, String),
+    #[error("Node `{0}` doesn't exist at `{1}`")]
+    UnknownNode(String, String),
+    #[error("Node `{0}` already exists at `{1}`")]
+    DuplicateNode(String, String),
+    #[error("Capture `{0}` is used before being defined at `{1}`")]
+    UndefinedCapture(String, String),
 }
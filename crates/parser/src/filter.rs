@@ -0,0 +1,214 @@
+use crate::{
+    ast::{Assertion, AssertionKind, NodeName, NodeSelector, Step, TestDefinition},
+    capture_references,
+};
+
+struct TagFilter {
+    include: Vec<String>,
+    exclude: Vec<String>,
+    /// Nodes (or globs and groups) paused and waiting for their `resume`, with
+    /// whether the `pause` was kept
+    paused: Vec<(NodeSelector, bool)>,
+    /// Variables captured by removed assertions
+    dropped_captures: Vec<String>,
+    /// Nodes added or removed so far, with whether they exist in the filtered test
+    nodes: Vec<(NodeName, bool)>,
+}
+
+/// Split the lists of nodes, to pair each node of a `resume` with its `pause`
+fn split_selector(selector: &NodeSelector) -> Vec<NodeSelector> {
+    match selector {
+        NodeSelector::List { nodes } => nodes.iter().cloned().map(NodeSelector::Name).collect(),
+        selector => vec![selector.clone()],
+    }
+}
+
+impl TagFilter {
+    fn has_tag(assertion: &Assertion, tags: &[String]) -> bool {
+        assertion.tags.iter().any(|tag| tags.contains(tag))
+    }
+
+    /// Whether the node exists in the filtered test, `None` if it isn't added or removed
+    fn node_exists(&self, name: &str) -> Option<bool> {
+        self.nodes
+            .iter()
+            .find(|(node, _)| node == name)
+            .map(|(_, exists)| *exists)
+    }
+
+    fn set_node_exists(&mut self, name: &str, exists: bool) {
+        self.nodes.retain(|(node, _)| node != name);
+        self.nodes.push((name.to_string(), exists));
+    }
+
+    /// Whether the nodes referred to by the assertion exist in the filtered test,
+    /// i.e. their `add node` (or not their `remove node`) was kept
+    fn nodes_exist(&self, parsed: &AssertionKind) -> bool {
+        match parsed {
+            AssertionKind::AddNode {
+                node_name,
+                template,
+                ..
+            } => {
+                self.node_exists(node_name) != Some(true)
+                    && self.node_exists(template) != Some(false)
+            }
+            AssertionKind::RemoveNode { node_name } => self.node_exists(node_name) != Some(false),
+            parsed => parsed
+                .node_selectors()
+                .into_iter()
+                .flat_map(split_selector)
+                .all(|node| match node {
+                    NodeSelector::Name(name) => self.node_exists(&name) != Some(false),
+                    _ => true,
+                }),
+        }
+    }
+
+    fn keep(&mut self, assertion: &mut Assertion) -> bool {
+        // follow the `add node`/`remove node` commands and the assertions capturing
+        // the referenced variables
+        let missing_node = !self.nodes_exist(&assertion.parsed);
+        let keep = self.keep_by_tags(assertion, missing_node)
+            && !capture_references(&assertion.original_line)
+                .iter()
+                .any(|name| self.dropped_captures.iter().any(|dropped| dropped == name));
+
+        if let AssertionKind::LogMatch {
+            capture: Some(capture),
+            ..
+        } = &assertion.parsed
+        {
+            self.dropped_captures
+                .retain(|dropped| dropped != &capture.var);
+            if !keep {
+                self.dropped_captures.push(capture.var.clone());
+            }
+        }
+
+        match &assertion.parsed {
+            AssertionKind::AddNode { node_name, .. } if keep => {
+                self.set_node_exists(node_name, true)
+            }
+            // not added, unless its `remove node` was dropped
+            AssertionKind::AddNode { node_name, .. }
+                if self.node_exists(node_name) != Some(true) =>
+            {
+                self.set_node_exists(node_name, false)
+            }
+            AssertionKind::RemoveNode { node_name } if keep => {
+                self.set_node_exists(node_name, false)
+            }
+            // not removed, unless its `add node` was dropped
+            AssertionKind::RemoveNode { node_name }
+                if self.node_exists(node_name) != Some(false) =>
+            {
+                self.set_node_exists(node_name, true)
+            }
+            _ => {}
+        }
+
+        keep
+    }
+
+    /// Whether the assertion is selected by the tags, `missing_node` drops it like an
+    /// excluded one (i.e. also a `pause`, so its `resume` follows)
+    fn keep_by_tags(&mut self, assertion: &mut Assertion, missing_node: bool) -> bool {
+        let excluded = missing_node || Self::has_tag(assertion, &self.exclude);
+        match &mut assertion.parsed {
+            AssertionKind::Pause { node_name } => {
+                self.paused.extend(
+                    split_selector(node_name)
+                        .into_iter()
+                        .map(|node| (node, !excluded)),
+                );
+                !excluded
+            }
+            AssertionKind::Resume { node_name } => {
+                let nodes = split_selector(node_name);
+                let total = nodes.len();
+                // follow the `pause` of each node
+                let kept: Vec<NodeSelector> = nodes
+                    .into_iter()
+                    .filter(|node| {
+                        match self.paused.iter().position(|(paused, _)| paused == node) {
+                            Some(idx) => self.paused.remove(idx).1,
+                            None => !excluded,
+                        }
+                    })
+                    .collect();
+
+                if kept.len() < total {
+                    // only resume the nodes still paused
+                    *node_name = match kept.as_slice() {
+                        [node] => node.clone(),
+                        nodes => NodeSelector::List {
+                            nodes: nodes
+                                .iter()
+                                .filter_map(|node| match node {
+                                    NodeSelector::Name(name) => Some(name.clone()),
+                                    _ => None,
+                                })
+                                .collect(),
+                        },
+                    };
+                }
+                !kept.is_empty()
+            }
+            kind if kind.is_command() => !excluded,
+            _ => !excluded && (self.include.is_empty() || Self::has_tag(assertion, &self.include)),
+        }
+    }
+
+    fn filter_steps(&mut self, steps: Vec<Step>) -> Vec<Step> {
+        steps
+            .into_iter()
+            .filter_map(|step| match step {
+                Step::Assertion(mut assertion) => self
+                    .keep(&mut assertion)
+                    .then_some(Step::Assertion(assertion)),
+                Step::Parallel { parallel } => {
+                    let parallel = self.filter_steps(parallel);
+                    (!parallel.is_empty()).then_some(Step::Parallel { parallel })
+                }
+                Step::Section(mut section) => {
+                    section.steps = self.filter_steps(section.steps);
+                    (!section.steps.is_empty()).then_some(Step::Section(section))
+                }
+            })
+            .collect()
+    }
+}
+
+/// Keep the assertions of the test definition selected by their tags.
+///
+/// An assertion is kept when it has one of the `include` tags (or `include` is empty)
+/// and none of the `exclude` tags. Commands (e.g. `pause`, `restart`) are only removed
+/// by the `exclude` tags, and a `resume` only resumes the nodes whose `pause` was kept,
+/// so the network stays in a consistent state. Likewise, the assertions using a
+/// captured variable are removed with the assertion capturing it, and the ones
+/// referring to an added (or removed) node follow its `add node` (or `remove node`). Empty sections and
+/// parallel blocks are removed.
+pub fn filter_by_tags(
+    test_def: TestDefinition,
+    include: &[String],
+    exclude: &[String],
+) -> TestDefinition {
+    let normalize = |tags: &[String]| -> Vec<String> {
+        tags.iter()
+            .map(|tag| tag.trim_start_matches('@').to_string())
+            .collect()
+    };
+    let mut filter = TagFilter {
+        include: normalize(include),
+        exclude: normalize(exclude),
+        paused: vec![],
+        dropped_captures: vec![],
+        nodes: vec![],
+    };
+
+    TestDefinition {
+        assertions: filter.filter_steps(test_def.assertions),
+        ..test_def
+    }
+}
@@ -1,4 +1,6 @@
-use std::path::PathBuf;
+use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
+use std::sync::OnceLock;
 use std::time::Duration;
 
 use pest::{
@@ -7,15 +9,56 @@ use pest::{
 };
 use pest_derive::Parser;
 
+use fs_err as fs;
+use regex::Regex;
+
 pub mod ast;
 mod errors;
+mod filter;
+pub mod metrics;
 use errors::ParserError;
 
-use ast::{Assertion, AssertionKind, Comparison, NodeName, ParaId, TestDefinition};
+use ast::{
+    Assertion, AssertionKind, Comparison, LabelMatcher, LabelOp, MetricExpr, MetricSelector,
+    NodeSelector, ParaId, Section, SectionKind, Step, TestDefinition, WindowFn,
+};
+
+pub use filter::filter_by_tags;
 
 #[cfg(test)]
 mod tests;
 
+/// Loads the content of a file, used to resolve the `Include:` directives.
+pub type FileLoader<'a> = dyn Fn(&Path) -> Result<String, String> + 'a;
+
+/// Looks up an environment variable, used to resolve the `${VAR}` references that
+/// are not variables of the test.
+pub type EnvLookup<'a> = dyn Fn(&str) -> Option<String> + 'a;
+
+struct ParseContext<'a> {
+    /// Variables passed to the parser, merged with the `let` bindings of the current file
+    vars: HashMap<String, String>,
+    loader: &'a FileLoader<'a>,
+    env: &'a EnvLookup<'a>,
+    /// Files being parsed, from the test file to the current included one
+    include_chain: Vec<PathBuf>,
+    /// Current `Test:` heading
+    test: Option<String>,
+    /// Current `Section:` heading
+    section: Option<String>,
+    /// Tags waiting for the next assertion, block or heading
+    pending_tags: Vec<String>,
+    /// Tags inherited from the current headings and blocks
+    scope_tags: Vec<String>,
+    /// Last line of the previous record of the file, and whether it's an assertion
+    /// (tags at the end of an assertion's line belong to it)
+    previous_record: Option<(usize, bool)>,
+    /// Variables captured by the previous assertions
+    captures: Vec<String>,
+    /// Variables captured in the current parallel block, only visible after it
+    parallel_captures: Option<Vec<String>>,
+}
+
 enum ScriptType {
     Javascript,
     Typescript,
@@ -30,21 +73,136 @@ const _GRAMMAR: &str = include_str!("zombienet.pest");
 #[grammar = "zombienet.pest"]
 pub struct ZombieNetParser;
 
-fn parse_name(pair: Pair<Rule>) -> Result<NodeName, ParserError> {
-    // get the first inner pair, since we don't want the `:`
+fn parse_name(pair: Pair<Rule>) -> Result<NodeSelector, ParserError> {
+    // get the first inner pair (the selector), since we don't want the `:`
     match pair.into_inner().next() {
-        Some(p) => Ok(p.as_str().to_string()),
+        Some(p) => parse_node_selector(p),
         None => Err(ParserError::Unexpected(String::from(
             "Rule should have an inner rule",
         ))),
     }
 }
 
+fn parse_node_selector(pair: Pair<Rule>) -> Result<NodeSelector, ParserError> {
+    let selector = match pair.into_inner().next() {
+        Some(p) => p,
+        None => {
+            return Err(ParserError::Unexpected(String::from(
+                "Rule should have an inner rule",
+            )))
+        }
+    };
+
+    let node_selector = match selector.as_rule() {
+        Rule::name => NodeSelector::Name(selector.as_str().to_string()),
+        Rule::node_list => NodeSelector::List {
+            nodes: selector
+                .into_inner()
+                .map(|p| p.as_str().to_string())
+                .collect(),
+        },
+        Rule::node_glob => NodeSelector::Glob {
+            glob: selector.as_str().to_string(),
+        },
+        Rule::node_group => NodeSelector::Group {
+            group: selector.into_inner().as_str().to_string(),
+        },
+        _ => return Err(ParserError::UnreachableRule(format!("{selector:?}"))),
+    };
+
+    Ok(node_selector)
+}
+
+fn parse_duration(pair: Pair<Rule>) -> Result<Duration, ParserError> {
+    let duration_str = pair.as_str();
+    let mut duration = Duration::ZERO;
+
+    for part in pair.into_inner() {
+        let mut pairs = part.into_inner();
+        let value_str = get_pair(&mut pairs, "value")?.as_str();
+        let value = value_str
+            .parse::<u64>()
+            .map_err(|_| ParserError::ParseError(format!("Can't parse {value_str} as u64")))?;
+        let unit = get_pair(&mut pairs, "time_unit")?
+            .into_inner()
+            .next()
+            .map(|p| p.as_rule());
+
+        let part_duration = match unit {
+            Some(Rule::milliseconds) => Some(Duration::from_millis(value)),
+            Some(Rule::seconds) => Some(Duration::from_secs(value)),
+            Some(Rule::minutes) => value.checked_mul(60).map(Duration::from_secs),
+            Some(Rule::hours) => value.checked_mul(60 * 60).map(Duration::from_secs),
+            _ => return Err(ParserError::UnreachableRule(duration_str.to_string())),
+        };
+
+        duration = part_duration
+            .and_then(|d| duration.checked_add(d))
+            .ok_or_else(|| ParserError::ParseError(format!("Duration {duration_str} overflows")))?;
+    }
+
+    Ok(duration)
+}
+
 fn parse_within(pair: Pair<Rule>) -> Result<Duration, ParserError> {
-    let within = pair.into_inner().as_str();
-    Ok(Duration::from_secs(within.parse::<u64>().map_err(
-        |_| ParserError::ParseError(format!("Can't parse {within} as u64")),
-    )?))
+    let mut pairs = pair.into_inner();
+    parse_duration(get_pair(&mut pairs, "duration")?)
+}
+
+/// Parse the `within` rule if it's the next pair
+fn parse_optional_within(pairs: &mut Pairs<Rule>) -> Result<Option<Duration>, ParserError> {
+    match pairs.peek() {
+        Some(pair) if pair.as_rule() == Rule::within => {
+            pairs.next();
+            Ok(Some(parse_within(pair)?))
+        }
+        _ => Ok(None),
+    }
+}
+
+/// Consume the negation rule if it's the next pair
+fn parse_negation(pairs: &mut Pairs<Rule>, negation_rule: Rule) -> bool {
+    match pairs.peek() {
+        Some(pair) if pair.as_rule() == negation_rule => {
+            pairs.next();
+            true
+        }
+        _ => false,
+    }
+}
+
+fn parse_timing_policy(pair: Pair<Rule>) -> Result<ast::TimingPolicy, ParserError> {
+    let mut retries = None;
+    let mut interval = None;
+
+    for inner_record in pair.into_inner() {
+        match inner_record.as_rule() {
+            Rule::retries => {
+                let retries_str = inner_record.into_inner().as_str();
+                retries = Some(retries_str.parse::<u32>().map_err(|_| {
+                    ParserError::ParseError(format!("Can't parse {retries_str} as u32"))
+                })?);
+            }
+            Rule::polling => {
+                let polling_str = inner_record.as_str().to_string();
+                let mut pairs = inner_record.into_inner();
+                let polling_interval = parse_duration(get_pair(&mut pairs, "duration")?)?;
+                if polling_interval.is_zero() {
+                    return Err(ParserError::ParseError(format!(
+                        "Interval of `{polling_str}` must be greater than zero"
+                    )));
+                }
+                interval = Some(polling_interval);
+            }
+            _ => {
+                return Err(ParserError::UnreachableRule(
+                    inner_record.as_str().to_string(),
+                ));
+            }
+        }
+    }
+
+    Ok(ast::TimingPolicy { retries, interval })
 }
 
 fn parse_para_id(pair: Pair<Rule>) -> Result<ParaId, ParserError> {
@@ -54,11 +212,23 @@ fn parse_para_id(pair: Pair<Rule>) -> Result<ParaId, ParserError> {
         .map_err(|_| ParserError::ParseError(format!("Can't parse {para_id_str} as u16")))
 }
 
-fn parse_taget_value(pair: Pair<Rule>) -> Result<u64, ParserError> {
+fn parse_taget_value(pair: Pair<Rule>) -> Result<ast::NumericValue, ParserError> {
     let target_str = pair.as_str();
+    if !target_str.contains(['.', 'e', 'E']) {
+        if let Ok(value) = target_str.parse::<i64>() {
+            return Ok(ast::NumericValue::Integer(value));
+        }
+    }
+
+    // integers out of the i64 range fall back to f64
     target_str
-        .parse::<u64>()
-        .map_err(|_| ParserError::ParseError(format!("Can't parse {target_str} as u64")))
+        .parse::<f64>()
+        .ok()
+        .filter(|value| value.is_finite())
+        .map(ast::NumericValue::Float)
+        .ok_or_else(|| {
+            ParserError::ParseError(format!("Can't parse {target_str} as a finite number"))
+        })
 }
 
 fn parse_math_ops(pair: Pair<Rule>) -> Result<ast::MathOps, ParserError> {
@@ -67,16 +237,77 @@ fn parse_math_ops(pair: Pair<Rule>) -> Result<ast::MathOps, ParserError> {
     let sign = match math_ops.as_rule() {
         Rule::plus => ast::MathOps::Plus,
         Rule::minus => ast::MathOps::Minus,
+        Rule::times => ast::MathOps::Multiply,
+        Rule::divided_by => ast::MathOps::Divide,
         _ => return Err(ParserError::UnreachableRule(format!("{math_ops:?}"))),
     };
 
     Ok(sign)
 }
 
+fn parse_metric_expr(pair: Pair<Rule>) -> Result<MetricExpr, ParserError> {
+    match pair.as_rule() {
+        Rule::metric_expr | Rule::metric_term => {
+            let mut pairs = pair.into_inner();
+            let mut expr = parse_metric_expr(get_pair(&mut pairs, "operand")?)?;
+            while let Some(op_pair) = pairs.next() {
+                let op = parse_math_ops(op_pair)?;
+                let rhs = parse_metric_expr(get_pair(&mut pairs, "operand")?)?;
+                if op == ast::MathOps::Divide
+                    && matches!(rhs, MetricExpr::Constant(value) if value.as_f64() == 0.0)
+                {
+                    return Err(ParserError::ParseError(
+                        "Division by zero in metrics expression".to_string(),
+                    ));
+                }
+                expr = MetricExpr::BinaryOp {
+                    op,
+                    lhs: Box::new(expr),
+                    rhs: Box::new(rhs),
+                };
+            }
+            Ok(expr)
+        }
+        Rule::metric_factor => parse_metric_expr(get_pair(&mut pair.into_inner(), "operand")?),
+        Rule::constant => Ok(MetricExpr::Constant(parse_taget_value(pair)?)),
+        Rule::metric_name => Ok(MetricExpr::Metric(parse_metric_selector(pair)?)),
+        _ => Err(ParserError::UnreachableRule(pair.as_str().to_string())),
+    }
+}
+
+fn parse_range(pair: Pair<Rule>) -> Result<ast::Comparison, ParserError> {
+    let range_str = pair.as_str().to_string();
+    let is_between = pair.as_rule() == Rule::between_range;
+    let mut inner_pairs = pair.into_inner();
+    let start = parse_taget_value(get_pair(&mut inner_pairs, "start")?)?;
+    let op = if is_between {
+        ast::Operator::IsBetween
+    } else {
+        match get_pair(&mut inner_pairs, "range_op")?.as_rule() {
+            Rule::inclusive_range_op => ast::Operator::IsBetween,
+            _ => ast::Operator::IsWithinRange,
+        }
+    };
+    let end = parse_taget_value(get_pair(&mut inner_pairs, "end")?)?;
+
+    if start.as_f64() > end.as_f64() {
+        return Err(ParserError::ParseError(format!(
+            "Invalid range {range_str}, start is greater than end"
+        )));
+    }
+
+    Ok(ast::Comparison {
+        op,
+        target_value: ast::TargetValue::Range(start, end),
+    })
+}
+
 fn parse_comparison(pair: Pair<Rule>) -> Result<ast::Comparison, ParserError> {
     let mut inner_pairs = pair.into_inner();
     let op_rule = get_pair(&mut inner_pairs, "op_rule")?;
     let op = match op_rule.as_rule() {
+        Rule::comparison => return parse_comparison(op_rule),
+        Rule::between_range | Rule::within_range => return parse_range(op_rule),
         Rule::op_lte => ast::Operator::IsAtMost,
         Rule::op_gte => ast::Operator::IsAtLeast,
         Rule::op_lt => ast::Operator::IsBelow,
@@ -88,19 +319,24 @@ fn parse_comparison(pair: Pair<Rule>) -> Result<ast::Comparison, ParserError> {
         }
     };
 
-    let target_value_str = get_pair(&mut inner_pairs, "target_value")?.as_str();
-    let target_value = target_value_str
-        .parse::<u64>()
-        .map_err(|_| ParserError::ParseError(format!("Can't parse {target_value_str} as u64")))?;
+    let target_pair = get_pair(&mut inner_pairs, "target_value")?;
+    let target_value = match target_pair.as_rule() {
+        Rule::bool_value => ast::TargetValue::Bool(target_pair.as_str() == "true"),
+        Rule::double_quoted_string => {
+            ast::TargetValue::Text(target_pair.as_str().trim_matches('"').to_string())
+        }
+        _ => ast::TargetValue::Single(parse_taget_value(target_pair)?),
+    };
 
     Ok(ast::Comparison { op, target_value })
 }
 
 fn parse_match_pattern_rule(
     record: Pair<Rule>,
-) -> Result<(String, String, String, Option<Duration>), ParserError> {
+) -> Result<(NodeSelector, bool, String, String, Option<Duration>), ParserError> {
     let mut pairs = record.into_inner();
     let name = parse_name(get_pair(&mut pairs, "name")?)?;
+    let negated = parse_negation(&mut pairs, Rule::negated_match);
 
     let mut explicit_match_type = false;
 
@@ -119,18 +355,27 @@ fn parse_match_pattern_rule(
     };
 
     let pattern = pattern_pair.as_str().trim_matches('"').to_owned();
-    let timeout: Option<Duration> = if let Some(within_rule) = pairs.next() {
-        Some(parse_within(within_rule)?)
-    } else {
-        None
-    };
+    // the capture (of log lines) is parsed by the caller
+    if matches!(pairs.peek(), Some(pair) if pair.as_rule() == Rule::capture_as) {
+        pairs.next();
+    }
+    let timeout = parse_optional_within(&mut pairs)?;
 
-    Ok((name, match_type, pattern, timeout))
+    Ok((name, negated, match_type, pattern, timeout))
 }
 
 fn parse_lines_count_match_pattern_rule(
     record: Pair<Rule>,
-) -> Result<(String, String, String, ast::Comparison, Option<Duration>), ParserError> {
+) -> Result<
+    (
+        NodeSelector,
+        String,
+        String,
+        ast::Comparison,
+        Option<Duration>,
+    ),
+    ParserError,
+> {
     let mut pairs = record.into_inner();
     let name = parse_name(get_pair(&mut pairs, "name")?)?;
 
@@ -154,9 +399,9 @@ fn parse_lines_count_match_pattern_rule(
 
     let cmp_rule = get_pair(&mut pairs, "cmp_rule")?;
     let comparison: ast::Comparison = match cmp_rule.as_rule() {
-        Rule::int => ast::Comparison {
+        Rule::number => ast::Comparison {
             op: ast::Operator::Equal,
-            target_value: parse_taget_value(cmp_rule)?,
+            target_value: ast::TargetValue::Single(parse_taget_value(cmp_rule)?),
         },
         Rule::comparison => parse_comparison(cmp_rule)?,
         _ => {
@@ -164,11 +409,7 @@ fn parse_lines_count_match_pattern_rule(
         }
     };
 
-    let timeout: Option<Duration> = if let Some(within_rule) = pairs.next() {
-        Some(parse_within(within_rule)?)
-    } else {
-        None
-    };
+    let timeout = parse_optional_within(&mut pairs)?;
 
     Ok((name, match_type, pattern, comparison, timeout))
 }
@@ -199,6 +440,8 @@ fn parse_custom_script_rule(
             Rule::within => {
                 timeout = Some(parse_within(inner_record)?);
             }
+            // parsed with the assertion
+            Rule::timing_policy => {}
             _ => {
                 return Err(ParserError::UnreachableRule(
                     inner_record.as_str().to_string(),
@@ -234,443 +477,1567 @@ fn parse_custom_script_rule(
     }
 }
 
-/// Parse a `feature` file and return a `json string`
-pub fn parse(unparsed_file: &str) -> Result<ast::TestDefinition, errors::ParserError> {
-    let mut pairs = match ZombieNetParser::parse(Rule::file, unparsed_file) {
-        Ok(p) => p,
-        Err(e) => return Err(errors::ParserError::ParseError(e.to_string())),
-    };
+fn parse_let_binding(line: &str) -> Option<(String, String)> {
+    let mut pairs = ZombieNetParser::parse(Rule::let_line, line.trim())
+        .ok()?
+        .next()?
+        .into_inner();
+    let name = pairs.next()?.as_str().to_string();
+    let value = pairs.next()?.as_str().trim().trim_matches('"').to_string();
 
-    let mut network: Option<String> = None;
-    let mut creds: Option<String> = None;
-    let mut description: Option<String> = None;
-    let mut assertions: Vec<Assertion> = vec![];
+    Some((name, value))
+}
 
-    let top_level_rule = if let Some(p) = pairs.next() {
-        p
-    } else {
-        return Err(ParserError::Unexpected(String::from(
-            "Invalid top level rule",
-        )));
-    };
+fn is_var_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
 
-    for record in top_level_rule.into_inner() {
-        let original_line = record.as_str().trim_end().to_string();
+/// Replace the `${VAR}` references in the line.
+///
+/// References are looked up in `vars` and then in `bindings`, the ones to `captures`
+/// are kept for the runner and the other ones fall back to the environment.
+/// References that are not valid names are kept as is.
+fn substitute_vars(
+    line: &str,
+    line_number: usize,
+    vars: &HashMap<String, String>,
+    bindings: &HashMap<String, String>,
+    captures: &[String],
+    env: &EnvLookup,
+) -> Result<String, ParserError> {
+    let mut resolved = String::with_capacity(line.len());
+    let mut rest = line;
+
+    while let Some(start) = rest.find("${") {
+        let inner = &rest[start + 2..];
+        let (name, reference_len) = match inner.find('}') {
+            Some(len) if is_var_name(inner[..len].trim()) => (inner[..len].trim(), len),
+            _ => {
+                resolved.push_str(&rest[..start + 2]);
+                rest = inner;
+                continue;
+            }
+        };
+
+        let end = start + 2 + reference_len + 1;
+        let value = match vars.get(name).or_else(|| bindings.get(name)) {
+            Some(value) => value.clone(),
+            None if captures.iter().any(|capture| capture == name) => rest[start..end].to_string(),
+            None => env(name)
+                .ok_or_else(|| ParserError::UndefinedVariable(name.to_string(), line_number))?,
+        };
+
+        resolved.push_str(&rest[..start]);
+        resolved.push_str(&value);
+        rest = &rest[end..];
+    }
+    resolved.push_str(rest);
+
+    Ok(resolved)
+}
+
+/// Sources of the values of the variables referenced by the files
+struct VarSources<'a> {
+    loader: &'a FileLoader<'a>,
+    env: &'a EnvLookup<'a>,
+}
 
+/// Add to `captures` the variables captured (by `capture [group] as NAME`) in the
+/// file, and in the files it includes. `include_chain` ends with the path of the file.
+fn declared_captures(
+    unparsed_file: &str,
+    include_chain: &mut Vec<PathBuf>,
+    vars: &HashMap<String, String>,
+    captures: &mut Vec<String>,
+    sources: &VarSources,
+) {
+    let mut bindings: HashMap<String, String> = HashMap::new();
+
+    for (idx, line) in unparsed_file.lines().enumerate() {
+        let trimmed = line.trim_start();
+        if trimmed.starts_with('#') || trimmed.starts_with("//") {
+            continue;
+        }
+
+        // undefined references are reported when resolving the file
+        let resolved = substitute_vars(line, idx + 1, vars, &bindings, captures, sources.env)
+            .unwrap_or_else(|_| line.to_string());
+        if let Some((name, value)) = parse_let_binding(&resolved) {
+            bindings.insert(name, value);
+            continue;
+        }
+
+        let Some(record) = ZombieNetParser::parse(Rule::capture_scan, &resolved)
+            .ok()
+            .and_then(|mut pairs| pairs.find(|p| p.as_rule() != Rule::tags))
+        else {
+            continue;
+        };
         match record.as_rule() {
-            Rule::description => {
-                description = Some(record.into_inner().as_str().to_owned());
+            Rule::log_match => captures.extend(
+                record
+                    .into_inner()
+                    .filter(|p| p.as_rule() == Rule::capture_as)
+                    .filter_map(|capture| capture.into_inner().last())
+                    .map(|var| var.as_str().to_string()),
+            ),
+            Rule::include => {
+                let current_dir = include_chain
+                    .last()
+                    .and_then(|p| p.parent())
+                    .map(Path::to_path_buf)
+                    .unwrap_or_default();
+                let path = normalize_path(&current_dir.join(record.into_inner().as_str()));
+                // cycles and load errors are reported when parsing the include
+                if include_chain.contains(&path) {
+                    continue;
+                }
+                if let Ok(fragment) = (sources.loader)(&path) {
+                    let vars = scoped_vars(vars, bindings.clone());
+                    include_chain.push(path);
+                    declared_captures(&fragment, include_chain, &vars, captures, sources);
+                    include_chain.pop();
+                }
             }
-            Rule::network => {
-                network = Some(record.into_inner().as_str().to_owned());
-            }
-            Rule::creds => {
-                let mut pairs = record.into_inner();
-                creds = if let Some(creds_rule) = pairs.next() {
-                    Some(creds_rule.into_inner().as_str().to_owned())
-                } else {
-                    Some(String::from("config"))
-                };
-            }
-            Rule::is_up => {
-                // Pairs should be in order:
-                // name, [timeout]
-                let mut pairs = record.into_inner();
-                let name = parse_name(get_pair(&mut pairs, "name")?)?;
+            _ => {}
+        }
+    }
+}
 
-                let timeout: Option<Duration> = if let Some(within_rule) = pairs.next() {
-                    Some(parse_within(within_rule)?)
-                } else {
-                    None
-                };
+/// Resolve the `let` bindings and variable references of the file, line by line,
+/// so bindings are only visible after their definition. References to `captures`
+/// are kept, their order is checked while parsing.
+/// Returns the resolved file and the bindings defined in it.
+fn resolve_vars(
+    unparsed_file: &str,
+    vars: &HashMap<String, String>,
+    captures: &[String],
+    env: &EnvLookup,
+) -> Result<(String, HashMap<String, String>), ParserError> {
+    let mut bindings: HashMap<String, String> = HashMap::new();
+    let mut resolved_lines: Vec<String> = vec![];
+
+    for (idx, line) in unparsed_file.lines().enumerate() {
+        let trimmed = line.trim_start();
+        if trimmed.starts_with('#') || trimmed.starts_with("//") {
+            resolved_lines.push(line.to_string());
+            continue;
+        }
 
-                let assertion = Assertion {
-                    parsed: AssertionKind::IsUp {
-                        node_name: name,
-                        timeout,
-                    },
-                    original_line,
-                };
+        let resolved = substitute_vars(line, idx + 1, vars, &bindings, captures, env)?;
+        if let Some((name, value)) = parse_let_binding(&resolved) {
+            bindings.insert(name, value);
+        }
+        resolved_lines.push(resolved);
+    }
+
+    Ok((resolved_lines.join("\n"), bindings))
+}
+
+/// Merge the `let` bindings of a file with the `vars`, the latter take precedence
+fn scoped_vars(
+    vars: &HashMap<String, String>,
+    bindings: HashMap<String, String>,
+) -> HashMap<String, String> {
+    let mut scoped = bindings;
+    scoped.extend(vars.iter().map(|(k, v)| (k.clone(), v.clone())));
+    scoped
+}
 
-                assertions.push(assertion);
+/// Lexically normalize the path (removing `.` and resolving `..`), used to detect include cycles
+fn normalize_path(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir if normalized.file_name().is_some() => {
+                normalized.pop();
             }
-            Rule::para_is_registered => {
-                // Pairs should be in order:
-                // name, para_id, [timeout]
-                let mut pairs = record.into_inner();
-                let name = parse_name(get_pair(&mut pairs, "name")?)?;
-                let para_id = parse_para_id(get_pair(&mut pairs, "para_id")?)?;
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
 
-                let timeout: Option<Duration> = if let Some(within_rule) = pairs.next() {
-                    Some(parse_within(within_rule)?)
-                } else {
-                    None
-                };
+fn format_include_chain(chain: &[PathBuf]) -> String {
+    chain
+        .iter()
+        .map(|path| match path.to_string_lossy() {
+            p if p.is_empty() => String::from("<input>"),
+            p => p.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join(" -> ")
+}
 
-                let assertion = Assertion {
-                    parsed: AssertionKind::ParaIsRegistered {
-                        node_name: name,
-                        para_id,
-                        timeout,
-                    },
-                    original_line,
-                };
+fn parse_fragment(path: &Path, ctx: &mut ParseContext) -> Result<Vec<Step>, ParserError> {
+    let unparsed_fragment = (ctx.loader)(path)
+        .map_err(|e| ParserError::IncludeLoad(path.to_string_lossy().to_string(), e))?;
+    let mut captures = ctx.captures.clone();
+    let sources = VarSources {
+        loader: ctx.loader,
+        env: ctx.env,
+    };
+    declared_captures(
+        &unparsed_fragment,
+        &mut ctx.include_chain.clone(),
+        &ctx.vars,
+        &mut captures,
+        &sources,
+    );
+    let (resolved_fragment, bindings) =
+        resolve_vars(&unparsed_fragment, &ctx.vars, &captures, ctx.env)?;
+    let mut pairs = ZombieNetParser::parse(Rule::fragment, &resolved_fragment)
+        .map_err(|e| ParserError::ParseError(e.to_string()))?;
+    let fragment = get_pair(&mut pairs, "fragment")?;
+
+    let parent_vars = std::mem::take(&mut ctx.vars);
+    ctx.vars = scoped_vars(&parent_vars, bindings);
+
+    let mut steps: Vec<Step> = vec![];
+    let result = fragment
+        .into_inner()
+        .try_for_each(|record| parse_body_record(record, ctx, &mut steps));
+
+    ctx.vars = parent_vars;
+    result.map(|_| steps)
+}
 
-                assertions.push(assertion);
-            }
-            Rule::para_block_height => {
-                // Pairs should be in order:
-                // name, para_id, comparison, [timeout]
-                let mut pairs = record.into_inner();
-                let name = parse_name(get_pair(&mut pairs, "name")?)?;
-                let para_id = parse_para_id(get_pair(&mut pairs, "para_id")?)?;
-                let comparison = parse_comparison(get_pair(&mut pairs, "comparison")?)?;
+/// Parse the assertions of an included file, relative to the including one
+fn parse_include(include_path: &Path, ctx: &mut ParseContext) -> Result<Vec<Step>, ParserError> {
+    let current_dir = ctx
+        .include_chain
+        .last()
+        .and_then(|p| p.parent())
+        .map(Path::to_path_buf)
+        .unwrap_or_default();
+    let path = normalize_path(&current_dir.join(include_path));
+
+    if ctx.include_chain.contains(&path) {
+        let mut chain = ctx.include_chain.clone();
+        chain.push(path);
+        return Err(ParserError::IncludeCycle(format_include_chain(&chain)));
+    }
 
-                let timeout: Option<Duration> = if let Some(within_rule) = pairs.next() {
-                    Some(parse_within(within_rule)?)
-                } else {
-                    None
-                };
+    ctx.include_chain.push(path.clone());
+    let chain = format_include_chain(&ctx.include_chain);
 
-                let assertion = Assertion {
-                    parsed: AssertionKind::ParaBlockHeight {
-                        node_name: name,
-                        para_id,
-                        op: comparison.op,
-                        target_value: comparison.target_value,
-                        timeout,
-                    },
-                    original_line,
-                };
+    let result = parse_fragment(&path, ctx);
+    ctx.include_chain.pop();
 
-                assertions.push(assertion);
-            }
-            Rule::calc_metrics => {
-                // Pairs should be in order:
-                // name, para_id, block_height, math_ops, finalized_height, comparison [timeout]
-                let mut pairs = record.into_inner();
-                let name = parse_name(get_pair(&mut pairs, "name")?)?;
-                let metric_name_a = get_pair(&mut pairs, "metric_name")?.as_str().to_string();
-                let math_ops = parse_math_ops(get_pair(&mut pairs, "math_ops")?)?;
-                let metric_name_b = get_pair(&mut pairs, "metric_name")?.as_str().to_string();
-                let comparison = parse_comparison(get_pair(&mut pairs, "comparison")?)?;
-                let timeout: Option<Duration> = if let Some(within_rule) = pairs.next() {
-                    Some(parse_within(within_rule)?)
-                } else {
-                    None
-                };
+    result.map_err(|e| match e {
+        // already reported with the full chain of includes
+        ParserError::Include(..) | ParserError::IncludeCycle(..) => e,
+        e => ParserError::Include(chain, Box::new(e)),
+    })
+}
 
-                let assertion = Assertion {
-                    parsed: AssertionKind::CalcMetrics {
-                        node_name: name.to_owned(),
-                        metric_name_a,
-                        math_ops,
-                        metric_name_b,
-                        op: comparison.op,
-                        target_value: comparison.target_value,
-                        timeout,
-                    },
-                    original_line,
-                };
+fn parse_label_op(pair: Pair<Rule>) -> Result<LabelOp, ParserError> {
+    match pair.as_str() {
+        "=" => Ok(LabelOp::Equal),
+        "!=" => Ok(LabelOp::NotEqual),
+        "=~" => Ok(LabelOp::RegexMatch),
+        "!~" => Ok(LabelOp::RegexNotMatch),
+        _ => Err(ParserError::Unexpected(pair.as_str().to_string())),
+    }
+}
 
-                assertions.push(assertion);
-            }
-            Rule::para_runtime_upgrade => {
-                // Pairs should be in order:
-                // name, para_id, file_or_uri, [timeout]
-                let mut pairs = record.into_inner();
-                let name = parse_name(get_pair(&mut pairs, "name")?)?;
-                let para_id = parse_para_id(get_pair(&mut pairs, "para_id")?)?;
-                let file_or_uri = get_pair(&mut pairs, "file_or_uri")?.as_str().to_string();
-                let timeout: Option<Duration> = if let Some(within_rule) = pairs.next() {
-                    Some(parse_within(within_rule)?)
-                } else {
-                    None
-                };
+fn parse_metric_selector(pair: Pair<Rule>) -> Result<MetricSelector, ParserError> {
+    let mut pairs = pair.into_inner();
+    let name = get_pair(&mut pairs, "metric_base_name")?
+        .as_str()
+        .to_string();
+    let mut matchers = vec![];
+    for matcher in pairs.flat_map(|label_matchers| label_matchers.into_inner()) {
+        let mut pairs = matcher.into_inner();
+        let label = get_pair(&mut pairs, "label_name")?.as_str().to_string();
+        let op = parse_label_op(get_pair(&mut pairs, "label_op")?)?;
+        let value = get_pair(&mut pairs, "label_value")?
+            .as_str()
+            .trim_matches('"')
+            .to_string();
+
+        if matches!(op, LabelOp::RegexMatch | LabelOp::RegexNotMatch) {
+            // prometheus regexes are fully anchored
+            Regex::new(&format!("^(?:{value})$"))
+                .map_err(|e| ParserError::InvalidRegex(value.clone(), e.to_string()))?;
+        }
+        matchers.push(LabelMatcher { label, op, value });
+    }
 
-                let assertion = Assertion {
-                    parsed: AssertionKind::ParaRuntimeUpgrade {
-                        node_name: name.to_owned(),
-                        para_id,
-                        file_or_uri,
-                        timeout,
-                    },
-                    original_line,
-                };
+    Ok(MetricSelector { name, matchers })
+}
 
-                assertions.push(assertion);
-            }
-            Rule::para_runtime_dummy_upgrade => {
-                // Pairs should be in order:
-                // name, para_id, [timeout]
-                let mut pairs = record.into_inner();
-                let name = parse_name(get_pair(&mut pairs, "name")?)?;
-                let para_id = parse_para_id(get_pair(&mut pairs, "para_id")?)?;
+/// Check that the runtime is a `.wasm` file (e.g. `.compact.wasm`) or a well-formed uri
+fn parse_runtime_source(pair: Pair<Rule>) -> Result<String, ParserError> {
+    let source = pair.as_str();
+    let is_valid = match pair.as_rule() {
+        Rule::uri => source
+            .split_once("://")
+            .map(|(_, rest)| rest.split('/').next().unwrap_or_default())
+            .is_some_and(is_valid_host),
+        _ => source.ends_with(".wasm"),
+    };
 
-                let timeout: Option<Duration> = if let Some(within_rule) = pairs.next() {
-                    Some(parse_within(within_rule)?)
-                } else {
-                    None
-                };
+    if is_valid {
+        Ok(source.to_string())
+    } else {
+        Err(ParserError::ParseError(format!(
+            "Invalid runtime `{source}`, expected a `.wasm` file or a valid uri"
+        )))
+    }
+}
 
-                let assertion = Assertion {
-                    parsed: AssertionKind::ParaRuntimeDummyUpgrade {
-                        node_name: name.to_owned(),
-                        para_id,
-                        timeout,
-                    },
-                    original_line,
-                };
+fn is_valid_host(host: &str) -> bool {
+    !host.is_empty()
+        && host.split('.').all(|label| {
+            !label.is_empty()
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+                && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        })
+}
 
-                assertions.push(assertion);
+fn parse_spec_version(pair: Pair<Rule>) -> Result<u32, ParserError> {
+    let version_str = get_pair(&mut pair.into_inner(), "spec_version")?.as_str();
+    version_str
+        .parse::<u32>()
+        .map_err(|_| ParserError::ParseError(format!("Can't parse {version_str} as u32")))
+}
+
+fn parse_arg_value(pair: Pair<Rule>) -> Result<ast::ArgValue, ParserError> {
+    let value_pair = get_pair(&mut pair.into_inner(), "arg_value")?;
+    let value_str = value_pair.as_str();
+    let value = match value_pair.as_rule() {
+        Rule::account_uri => ast::ArgValue::Account(value_str.to_string()),
+        Rule::hex_value => {
+            if value_str.len() % 2 != 0 {
+                return Err(ParserError::ParseError(format!(
+                    "Hex value {value_str} has an odd number of digits"
+                )));
             }
-            Rule::histogram => {
-                // Pairs should be in order:
-                // name, metric_name, cmp, buckets, [timeout]
-                let mut pairs = record.into_inner();
-                let name = parse_name(get_pair(&mut pairs, "name")?)?;
-                let metric_name = get_pair(&mut pairs, "metric_name")?.as_str().to_string();
-                let cmp_rule = get_pair(&mut pairs, "cmp_rule")?;
-                let cmp: ast::Comparison = match cmp_rule.as_rule() {
-                    Rule::int => ast::Comparison {
-                        op: ast::Operator::Equal,
-                        target_value: parse_taget_value(cmp_rule)?,
-                    },
-                    Rule::comparison => parse_comparison(cmp_rule)?,
-                    _ => {
-                        return Err(ParserError::UnreachableRule(pairs.as_str().to_string()));
-                    }
-                };
-                let buckets = get_pair(&mut pairs, "buckets")?
-                    .as_str()
-                    .trim_matches(|x| x == '[' || x == ']')
-                    .split(',')
-                    .map(|x| x.trim().trim_matches('"').to_string())
-                    .collect();
-
-                let timeout: Option<Duration> = if let Some(within_rule) = pairs.next() {
-                    Some(parse_within(within_rule)?)
-                } else {
-                    None
-                };
+            ast::ArgValue::Hex(value_str.to_string())
+        }
+        Rule::bool_value => ast::ArgValue::Bool(value_str == "true"),
+        Rule::number => ast::ArgValue::Number(parse_taget_value(value_pair)?),
+        Rule::double_quoted_string => ast::ArgValue::Text(value_str.trim_matches('"').to_string()),
+        Rule::arg_list => ast::ArgValue::List(
+            value_pair
+                .into_inner()
+                .map(parse_arg_value)
+                .collect::<Result<Vec<_>, _>>()?,
+        ),
+        _ => return Err(ParserError::UnreachableRule(value_str.to_string())),
+    };
 
-                let assertion = Assertion {
-                    parsed: AssertionKind::Histogram {
-                        node_name: name,
-                        metric_name,
-                        op: cmp.op,
-                        target_value: cmp.target_value,
-                        buckets,
-                        timeout,
-                    },
-                    original_line,
-                };
+    Ok(value)
+}
 
-                assertions.push(assertion);
-            }
-            Rule::report => {
-                // Pairs should be in order:
-                // name, metric_name, cmp, [timeout]
-                let mut pairs = record.into_inner();
-                let name = parse_name(get_pair(&mut pairs, "name")?)?;
-                let metric_name = get_pair(&mut pairs, "metric_name")?.as_str().to_string();
-                let cmp = parse_comparison(get_pair(&mut pairs, "cmp_rule")?)?;
-                let timeout: Option<Duration> = if let Some(within_rule) = pairs.next() {
-                    Some(parse_within(within_rule)?)
-                } else {
-                    None
-                };
+fn parse_path_segment(pair: Pair<Rule>) -> Result<ast::PathSegment, ParserError> {
+    let rule = pair.as_rule();
+    let value_str = get_pair(&mut pair.into_inner(), "path_segment")?.as_str();
+    match rule {
+        Rule::field_segment => Ok(ast::PathSegment::Field(value_str.to_string())),
+        Rule::index_segment => value_str
+            .parse::<u32>()
+            .map(ast::PathSegment::Index)
+            .map_err(|_| ParserError::ParseError(format!("Can't parse {value_str} as u32"))),
+        _ => Err(ParserError::UnreachableRule(value_str.to_string())),
+    }
+}
 
-                let assertion = Assertion {
-                    parsed: AssertionKind::Report {
-                        node_name: name.to_owned(),
-                        metric_name,
-                        op: cmp.op,
-                        target_value: cmp.target_value,
-                        timeout,
-                    },
-                    original_line,
-                };
+/// Parse the `with args "..."` of a node command if it's the next pair
+fn parse_node_args(pairs: &mut Pairs<Rule>) -> Result<Vec<String>, ParserError> {
+    match pairs.peek() {
+        Some(pair) if pair.as_rule() == Rule::node_args => {
+            pairs.next();
+            let args = get_pair(&mut pair.into_inner(), "args")?;
+            Ok(args
+                .as_str()
+                .trim_matches('"')
+                .split_whitespace()
+                .map(str::to_string)
+                .collect())
+        }
+        _ => Ok(vec![]),
+    }
+}
 
-                assertions.push(assertion);
-            }
-            Rule::log_match => {
-                let (name, match_type, pattern, timeout) = parse_match_pattern_rule(record)?;
+/// Parse the `for <duration>` of a chaos command if it's the next pair
+fn parse_chaos_duration(pairs: &mut Pairs<Rule>) -> Result<Option<Duration>, ParserError> {
+    match pairs.next() {
+        Some(pair) => Ok(Some(parse_duration(get_pair(
+            &mut pair.into_inner(),
+            "duration",
+        )?)?)),
+        None => Ok(None),
+    }
+}
 
-                let assertion = Assertion {
-                    parsed: AssertionKind::LogMatch {
-                        node_name: name,
-                        match_type,
-                        pattern,
-                        timeout,
-                    },
-                    original_line,
-                };
+fn parse_percentage(pair: Pair<Rule>) -> Result<ast::NumericValue, ParserError> {
+    let percentage_str = pair.as_str();
+    let value = percentage_str.trim_end_matches('%');
+    let percentage = if value.contains('.') {
+        value.parse::<f64>().map(ast::NumericValue::Float).ok()
+    } else {
+        value.parse::<i64>().map(ast::NumericValue::Integer).ok()
+    }
+    .filter(|percentage| (0.0..=100.0).contains(&percentage.as_f64()))
+    .ok_or_else(|| {
+        ParserError::ParseError(format!(
+            "Invalid percentage {percentage_str}, expected a value between 0% and 100%"
+        ))
+    })?;
+
+    Ok(percentage)
+}
 
-                assertions.push(assertion);
-            }
-            Rule::count_log_match => {
-                let (name, match_type, pattern, comparison, timeout) =
-                    parse_lines_count_match_pattern_rule(record)?;
-
-                let assertion = Assertion {
-                    parsed: AssertionKind::CountLogMatch {
-                        node_name: name,
-                        match_type,
-                        pattern,
-                        target_value: comparison.target_value,
-                        op: comparison.op,
-                        timeout,
-                    },
-                    original_line,
-                };
+fn parse_quantile(pair: Pair<Rule>) -> Result<ast::NumericValue, ParserError> {
+    let quantile_str = pair.as_str();
+    let value_pair = get_pair(&mut pair.into_inner(), "quantile")?;
+    let quantile = match value_pair.as_rule() {
+        Rule::percentile => value_pair.as_str()[1..]
+            .parse::<f64>()
+            .map(|percentile| percentile / 100.0),
+        _ => value_pair.as_str().parse::<f64>(),
+    }
+    .map_err(|_| ParserError::ParseError(format!("Can't parse {quantile_str} as quantile")))?;
 
-                assertions.push(assertion);
-            }
-            Rule::trace => {
-                // Pairs should be in order:
-                // name, span_id, pattern, [timeout]
-                let mut pairs = record.into_inner();
-                let name = parse_name(get_pair(&mut pairs, "name")?)?;
-                let span_id = get_pair(&mut pairs, "span_id")?.as_str().to_string();
-                let pattern = get_pair(&mut pairs, "pattern")?.as_str().to_string();
+    if !(0.0..=1.0).contains(&quantile) {
+        return Err(ParserError::ParseError(format!(
+            "Quantile `{quantile_str}` must be between 0 and 1 (or p0 and p100)"
+        )));
+    }
 
-                let timeout: Option<Duration> = if let Some(within_rule) = pairs.next() {
-                    Some(parse_within(within_rule)?)
-                } else {
-                    None
-                };
+    Ok(ast::NumericValue::Float(quantile))
+}
 
-                let assertion = Assertion {
-                    parsed: AssertionKind::Trace {
-                        node_name: name.to_owned(),
-                        span_id,
-                        pattern,
-                        timeout,
-                    },
-                    original_line,
-                };
+fn parse_metric_window(
+    pair: Pair<Rule>,
+) -> Result<(WindowFn, MetricSelector, Duration), ParserError> {
+    let mut pairs = pair.into_inner();
+    let window_fn = match get_pair(&mut pairs, "window_fn")?.as_str() {
+        "rate" => WindowFn::Rate,
+        "increase" => WindowFn::Increase,
+        other => return Err(ParserError::Unexpected(other.to_string())),
+    };
+    let metric_name = parse_metric_selector(get_pair(&mut pairs, "metric_name")?)?;
+    let window = parse_duration(get_pair(&mut pairs, "duration")?)?;
+    if window.is_zero() {
+        return Err(ParserError::ParseError(format!(
+            "Window of `{}` must be greater than zero",
+            metric_name.name
+        )));
+    }
+
+    Ok((window_fn, metric_name, window))
+}
+
+fn parse_tags(pair: Pair<Rule>) -> Vec<String> {
+    pair.into_inner()
+        .map(|tag| tag.as_str().trim_start_matches('@').to_string())
+        .collect()
+}
+
+/// Append the `tags` not already in `inherited`
+fn merge_tags(inherited: &[String], tags: &[String]) -> Vec<String> {
+    let mut merged = inherited.to_vec();
+    for tag in tags {
+        if !merged.contains(tag) {
+            merged.push(tag.clone());
+        }
+    }
+    merged
+}
+
+fn check_no_pending_tags(ctx: &ParseContext) -> Result<(), ParserError> {
+    if ctx.pending_tags.is_empty() {
+        Ok(())
+    } else {
+        Err(ParserError::ParseError(format!(
+            "Tags @{} are not followed by an assertion, block or heading",
+            ctx.pending_tags.join(" @")
+        )))
+    }
+}
 
-                assertions.push(assertion);
+/// Check that the steps of a parallel block can run concurrently
+fn check_parallel_steps(steps: &[Step]) -> Result<(), ParserError> {
+    for step in steps {
+        match step {
+            Step::Assertion(assertion) if assertion.parsed.is_command() => {
+                return Err(ParserError::InvalidParallelBlock(format!(
+                    "command `{}` is an ordering barrier and can't run in parallel",
+                    assertion.original_line
+                )));
             }
-            Rule::system_event => {
-                let (name, match_type, pattern, timeout) = parse_match_pattern_rule(record)?;
+            Step::Parallel { .. } | Step::Section(_) => {
+                return Err(ParserError::InvalidParallelBlock(String::from(
+                    "nested parallel blocks are not supported",
+                )));
+            }
+            Step::Assertion(_) => {}
+        }
+    }
 
-                let assertion = Assertion {
-                    parsed: AssertionKind::SystemEvent {
-                        node_name: name,
-                        match_type,
-                        pattern,
-                        timeout,
-                    },
-                    original_line,
-                };
+    Ok(())
+}
+
+/// Names of the `${VAR}` references left in the line, i.e. the references to captures
+pub(crate) fn capture_references(line: &str) -> Vec<&str> {
+    let mut names = vec![];
+    let mut rest = line;
+    while let Some(start) = rest.find("${") {
+        rest = &rest[start + 2..];
+        let Some(end) = rest.find('}') else {
+            break;
+        };
+        let name = rest[..end].trim();
+        if is_var_name(name) {
+            names.push(name);
+        }
+    }
+
+    names
+}
+
+/// Check that the `${VAR}` references left in the line (i.e. to captures) are captured
+/// by a previous assertion
+fn check_capture_references(line: &str, captures: &[String]) -> Result<(), ParserError> {
+    match capture_references(line)
+        .into_iter()
+        .find(|name| !captures.iter().any(|capture| capture == name))
+    {
+        Some(name) => Err(ParserError::UndefinedCapture(
+            name.to_string(),
+            line.to_string(),
+        )),
+        None => Ok(()),
+    }
+}
 
-                assertions.push(assertion);
+fn parse_capture(
+    pair: Pair<Rule>,
+    negated: bool,
+    match_type: &str,
+    pattern: &str,
+    ctx: &ParseContext,
+) -> Result<ast::Capture, ParserError> {
+    let mut names: Vec<String> = pair
+        .into_inner()
+        .map(|name| name.as_str().to_string())
+        .collect();
+    let var = names
+        .pop()
+        .ok_or_else(|| ParserError::MissingFields(String::from("capture variable")))?;
+    let group = names.pop();
+
+    if negated || match_type != "regex" {
+        return Err(ParserError::ParseError(format!(
+            "Can't capture `{var}`, captures need a (not negated) regex match"
+        )));
+    }
+    if ctx.vars.contains_key(&var) {
+        return Err(ParserError::ParseError(format!(
+            "Can't capture `{var}`, it's already defined as a variable"
+        )));
+    }
+
+    // references to other captures are replaced by the runner, and the js syntax
+    // of named groups, `(?<name>...)`, is accepted
+    static REFERENCE_RE: OnceLock<Regex> = OnceLock::new();
+    let reference_re = REFERENCE_RE.get_or_init(|| {
+        Regex::new(r"\$\{\s*[A-Za-z_]\w*\s*\}").expect("reference regex should be valid")
+    });
+    let regex = Regex::new(&reference_re.replace_all(pattern, "").replace("(?<", "(?P<"))
+        .map_err(|e| ParserError::InvalidRegex(pattern.to_string(), e.to_string()))?;
+    let groups: Vec<&str> = regex.capture_names().flatten().collect();
+    let group = match (group, groups.as_slice()) {
+        (Some(group), groups) if groups.contains(&group.as_str()) => group,
+        (None, [group]) => group.to_string(),
+        (Some(group), _) => {
+            return Err(ParserError::ParseError(format!(
+                "Regex `{pattern}` has no named group `{group}`"
+            )))
+        }
+        (None, _) => {
+            return Err(ParserError::ParseError(format!(
+                "Regex `{pattern}` should have exactly one named group to capture `{var}`, \
+                 e.g. `(?P<name>...)` (or use `capture <group> as {var}`)"
+            )))
+        }
+    };
+
+    Ok(ast::Capture { group, var })
+}
+
+/// Collect the nodes introduced by `add node`, i.e. the ones added before being
+/// removed (the nodes of the network can be removed and added again).
+fn added_nodes(steps: &[Step], seen: &mut Vec<String>, added: &mut Vec<String>) {
+    for step in steps {
+        match step {
+            Step::Assertion(assertion) => match &assertion.parsed {
+                AssertionKind::AddNode { node_name, .. } if !seen.contains(node_name) => {
+                    seen.push(node_name.clone());
+                    added.push(node_name.clone());
+                }
+                AssertionKind::RemoveNode { node_name } if !seen.contains(node_name) => {
+                    seen.push(node_name.clone());
+                }
+                _ => {}
+            },
+            Step::Parallel { parallel } => added_nodes(parallel, seen, added),
+            Step::Section(section) => added_nodes(&section.steps, seen, added),
+        }
+    }
+}
+
+/// Check that the steps only refer to nodes that exist at that point of the test,
+/// i.e. nodes that are not removed by an earlier `remove node`, or not yet added
+/// by their `add node`.
+///
+/// The nodes of the network are not known by the parser, so globs and groups
+/// are resolved (and checked) by the runner.
+fn check_node_references(
+    steps: &[Step],
+    added: &mut Vec<String>,
+    missing: &mut Vec<String>,
+) -> Result<(), ParserError> {
+    for step in steps {
+        let assertion = match step {
+            Step::Assertion(assertion) => assertion,
+            Step::Parallel { parallel } => {
+                check_node_references(parallel, added, missing)?;
+                continue;
             }
-            Rule::custom_js => {
-                let parsed = parse_custom_script_rule(record, ScriptType::Javascript)?;
-                let assertion = Assertion {
-                    parsed,
-                    original_line,
-                };
+            Step::Section(section) => {
+                check_node_references(&section.steps, added, missing)?;
+                continue;
+            }
+        };
+        let unknown_node = |name: &str| {
+            ParserError::UnknownNode(name.to_string(), assertion.original_line.clone())
+        };
+
+        match &assertion.parsed {
+            AssertionKind::AddNode {
+                node_name,
+                template,
+                ..
+            } => {
+                if template == node_name {
+                    return Err(ParserError::ParseError(format!(
+                        "Node `{node_name}` can't be its own template at `{}`",
+                        assertion.original_line
+                    )));
+                }
+                if missing.contains(template) {
+                    return Err(unknown_node(template));
+                }
+                if added.contains(node_name) {
+                    return Err(ParserError::DuplicateNode(
+                        node_name.clone(),
+                        assertion.original_line.clone(),
+                    ));
+                }
+                missing.retain(|name| name != node_name);
+                added.push(node_name.clone());
+            }
+            AssertionKind::RemoveNode { node_name } => {
+                if missing.contains(node_name) {
+                    return Err(unknown_node(node_name));
+                }
+                added.retain(|name| name != node_name);
+                missing.push(node_name.clone());
+            }
+            parsed => {
+                for selector in parsed.node_selectors() {
+                    let names = match selector {
+                        NodeSelector::Name(name) => std::slice::from_ref(name),
+                        NodeSelector::List { nodes } => nodes.as_slice(),
+                        NodeSelector::Glob { .. } | NodeSelector::Group { .. } => &[],
+                    };
+                    if let Some(name) = names.iter().find(|name| missing.contains(name)) {
+                        return Err(unknown_node(name));
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
 
-                assertions.push(assertion);
+/// Parse an assertion, command or block (or the ones from an included file) into `steps`
+fn parse_body_record(
+    record: Pair<Rule>,
+    ctx: &mut ParseContext,
+    steps: &mut Vec<Step>,
+) -> Result<(), ParserError> {
+    let original_line = record.as_str().trim_end().to_string();
+    let timing = match record
+        .clone()
+        .into_inner()
+        .find(|p| p.as_rule() == Rule::timing_policy)
+    {
+        Some(timing_rule) => Some(parse_timing_policy(timing_rule)?),
+        None => None,
+    };
+
+    let line_tags = match record.as_rule() {
+        Rule::EOI | Rule::comment | Rule::let_binding | Rule::tags => vec![],
+        _ => std::mem::take(&mut ctx.pending_tags),
+    };
+
+    let (line, _) = record.as_span().start_pos().line_col();
+    let end_line = line + original_line.matches('\n').count();
+    let is_assertion = !matches!(
+        record.as_rule(),
+        Rule::EOI | Rule::comment | Rule::let_binding | Rule::tags | Rule::include | Rule::parallel
+    );
+    let previous_record = ctx.previous_record.replace((end_line, is_assertion));
+
+    let parsed = match record.as_rule() {
+        Rule::tags => {
+            let tags = parse_tags(record);
+            match previous_record {
+                // tags at the end of the line of an assertion
+                Some((previous_line, true)) if previous_line == line => {
+                    if let Some(Step::Assertion(assertion)) = steps.last_mut() {
+                        assertion.tags = merge_tags(&assertion.tags, &tags);
+                    }
+                }
+                Some((previous_line, false)) if previous_line == line => {
+                    return Err(ParserError::ParseError(format!(
+                        "Tags @{} should be at the start of the line, or at the end of an assertion",
+                        tags.join(" @")
+                    )));
+                }
+                _ => ctx.pending_tags.extend(tags),
             }
-            Rule::custom_ts => {
-                let parsed = parse_custom_script_rule(record, ScriptType::Typescript)?;
-                let assertion = Assertion {
-                    parsed,
-                    original_line,
-                };
+            return Ok(());
+        }
+        Rule::include => {
+            let include_path = get_pair(&mut record.into_inner(), "file_path")?.as_str();
+            let parent_scope_tags = ctx.scope_tags.clone();
+            ctx.scope_tags = merge_tags(&ctx.scope_tags, &line_tags);
+            // the lines of the included file aren't the ones of this file
+            ctx.previous_record = None;
+            let result = parse_include(Path::new(include_path), ctx);
+            ctx.scope_tags = parent_scope_tags;
+            ctx.previous_record = Some((end_line, false));
+
+            steps.extend(result?);
+            return Ok(());
+        }
+        Rule::parallel => {
+            let parent_scope_tags = ctx.scope_tags.clone();
+            ctx.scope_tags = merge_tags(&ctx.scope_tags, &line_tags);
+            ctx.parallel_captures = Some(vec![]);
+            let mut parallel: Vec<Step> = vec![];
+            let result = record
+                .into_inner()
+                .try_for_each(|inner_record| parse_body_record(inner_record, ctx, &mut parallel));
+            ctx.scope_tags = parent_scope_tags;
+            ctx.previous_record = Some((end_line, false));
+            let block_captures = ctx.parallel_captures.take().unwrap_or_default();
+            ctx.captures.extend(block_captures);
+
+            result?;
+            check_no_pending_tags(ctx)?;
+            check_parallel_steps(&parallel)?;
+
+            steps.push(Step::Parallel { parallel });
+            return Ok(());
+        }
+        Rule::is_up => {
+            // Pairs should be in order:
+            // name, [timeout]
+            let mut pairs = record.into_inner();
+            let name = parse_name(get_pair(&mut pairs, "name")?)?;
+
+            let timeout = parse_optional_within(&mut pairs)?;
 
-                assertions.push(assertion);
+            AssertionKind::IsUp {
+                node_name: name,
+                timeout,
             }
-            Rule::custom_sh => {
-                let parsed = parse_custom_script_rule(record, ScriptType::Shellscript)?;
-                let assertion = Assertion {
-                    parsed,
-                    original_line,
-                };
+        }
+        Rule::para_is_registered => {
+            // Pairs should be in order:
+            // name, para_id, [negated], [timeout]
+            let mut pairs = record.into_inner();
+            let name = parse_name(get_pair(&mut pairs, "name")?)?;
+            let para_id = parse_para_id(get_pair(&mut pairs, "para_id")?)?;
+            let negated = parse_negation(&mut pairs, Rule::negated_registered);
+
+            let timeout = parse_optional_within(&mut pairs)?;
+
+            AssertionKind::ParaIsRegistered {
+                node_name: name,
+                para_id,
+                negated,
+                timeout,
+            }
+        }
+        Rule::para_block_height => {
+            // Pairs should be in order:
+            // name, para_id, comparison, [timeout]
+            let mut pairs = record.into_inner();
+            let name = parse_name(get_pair(&mut pairs, "name")?)?;
+            let para_id = parse_para_id(get_pair(&mut pairs, "para_id")?)?;
+            let comparison = parse_comparison(get_pair(&mut pairs, "comparison")?)?;
+
+            let timeout = parse_optional_within(&mut pairs)?;
+
+            AssertionKind::ParaBlockHeight {
+                node_name: name,
+                para_id,
+                op: comparison.op,
+                target_value: comparison.target_value,
+                timeout,
+            }
+        }
+        Rule::para_finalized_height => {
+            // Pairs should be in order:
+            // name, para_id, comparison, [timeout]
+            let mut pairs = record.into_inner();
+            let name = parse_name(get_pair(&mut pairs, "name")?)?;
+            let para_id = parse_para_id(get_pair(&mut pairs, "para_id")?)?;
+            let comparison = parse_comparison(get_pair(&mut pairs, "comparison")?)?;
+            let timeout = parse_optional_within(&mut pairs)?;
+
+            AssertionKind::ParaFinalizedHeight {
+                node_name: name,
+                para_id,
+                op: comparison.op,
+                target_value: comparison.target_value,
+                timeout,
+            }
+        }
+        Rule::para_block_production => {
+            // Pairs should be in order:
+            // name, para_id, cmp, timeout (the blocks are counted within it)
+            let mut pairs = record.into_inner();
+            let name = parse_name(get_pair(&mut pairs, "name")?)?;
+            let para_id = parse_para_id(get_pair(&mut pairs, "para_id")?)?;
+            let cmp_rule = get_pair(&mut pairs, "cmp_rule")?;
+            let cmp: ast::Comparison = match cmp_rule.as_rule() {
+                Rule::number => ast::Comparison {
+                    op: ast::Operator::Equal,
+                    target_value: ast::TargetValue::Single(parse_taget_value(cmp_rule)?),
+                },
+                Rule::comparison => parse_comparison(cmp_rule)?,
+                _ => {
+                    return Err(ParserError::UnreachableRule(pairs.as_str().to_string()));
+                }
+            };
+            let timeout = parse_optional_within(&mut pairs)?;
+
+            AssertionKind::ParaBlockProduction {
+                node_name: name,
+                para_id,
+                op: cmp.op,
+                target_value: cmp.target_value,
+                timeout,
+            }
+        }
+        Rule::calc_metrics => {
+            // Pairs should be in order:
+            // name, metric_expr, comparison [timeout]
+            let mut pairs = record.into_inner();
+            let name = parse_name(get_pair(&mut pairs, "name")?)?;
+            let expression = parse_metric_expr(get_pair(&mut pairs, "metric_expr")?)?;
+            let comparison = parse_comparison(get_pair(&mut pairs, "comparison")?)?;
+            let timeout = parse_optional_within(&mut pairs)?;
+            let operands = match &expression {
+                MetricExpr::BinaryOp {
+                    op: math_ops @ (ast::MathOps::Plus | ast::MathOps::Minus),
+                    lhs,
+                    rhs,
+                } => match (lhs.as_ref(), rhs.as_ref()) {
+                    (MetricExpr::Metric(a), MetricExpr::Metric(b)) => {
+                        Some(ast::CalcMetricsOperands {
+                            metric_name_a: a.clone(),
+                            math_ops: *math_ops,
+                            metric_name_b: b.clone(),
+                        })
+                    }
+                    _ => None,
+                },
+                _ => None,
+            };
+
+            AssertionKind::CalcMetrics {
+                node_name: name.to_owned(),
+                expression,
+                operands,
+                op: comparison.op,
+                target_value: comparison.target_value,
+                timeout,
+            }
+        }
+        Rule::para_runtime_upgrade => {
+            // Pairs should be in order:
+            // name, para_id, file_or_uri, [expected_spec_version], [timeout]
+            let mut pairs = record.into_inner();
+            let name = parse_name(get_pair(&mut pairs, "name")?)?;
+            let para_id = parse_para_id(get_pair(&mut pairs, "para_id")?)?;
+            let file_or_uri = parse_runtime_source(get_pair(&mut pairs, "file_or_uri")?)?;
+            let expected_spec_version = match pairs.peek() {
+                Some(pair) if pair.as_rule() == Rule::expected_spec_version => {
+                    pairs.next();
+                    Some(parse_spec_version(pair)?)
+                }
+                _ => None,
+            };
+            let timeout = parse_optional_within(&mut pairs)?;
+
+            AssertionKind::ParaRuntimeUpgrade {
+                node_name: name.to_owned(),
+                para_id,
+                file_or_uri,
+                expected_spec_version,
+                timeout,
+            }
+        }
+        Rule::para_runtime_spec_version => {
+            // Pairs should be in order:
+            // name, para_id, comparison, [timeout]
+            let mut pairs = record.into_inner();
+            let name = parse_name(get_pair(&mut pairs, "name")?)?;
+            let para_id = parse_para_id(get_pair(&mut pairs, "para_id")?)?;
+            let comparison = parse_comparison(get_pair(&mut pairs, "comparison")?)?;
+            // spec_version is an u32, like the expected one of the upgrades
+            let is_spec_version = |value: &ast::NumericValue| matches!(value, ast::NumericValue::Integer(v) if u32::try_from(*v).is_ok());
+            let valid = match &comparison.target_value {
+                ast::TargetValue::Single(value) => is_spec_version(value),
+                ast::TargetValue::Range(start, end) => {
+                    is_spec_version(start) && is_spec_version(end)
+                }
+                _ => false,
+            };
+            if !valid {
+                return Err(ParserError::ParseError(format!(
+                    "Invalid spec_version target at `{original_line}`, expected an unsigned integer"
+                )));
+            }
+            let timeout = parse_optional_within(&mut pairs)?;
+
+            AssertionKind::ParaRuntimeSpecVersion {
+                node_name: name,
+                para_id,
+                op: comparison.op,
+                target_value: comparison.target_value,
+                timeout,
+            }
+        }
+        Rule::para_runtime_dummy_upgrade => {
+            // Pairs should be in order:
+            // name, para_id, [timeout]
+            let mut pairs = record.into_inner();
+            let name = parse_name(get_pair(&mut pairs, "name")?)?;
+            let para_id = parse_para_id(get_pair(&mut pairs, "para_id")?)?;
+
+            let timeout = parse_optional_within(&mut pairs)?;
+
+            AssertionKind::ParaRuntimeDummyUpgrade {
+                node_name: name.to_owned(),
+                para_id,
+                timeout,
+            }
+        }
+        Rule::histogram => {
+            // Pairs should be in order:
+            // name, metric_name, cmp, buckets, [timeout]
+            let mut pairs = record.into_inner();
+            let name = parse_name(get_pair(&mut pairs, "name")?)?;
+            let metric_name = parse_metric_selector(get_pair(&mut pairs, "metric_name")?)?;
+            let cmp_rule = get_pair(&mut pairs, "cmp_rule")?;
+            let cmp: ast::Comparison = match cmp_rule.as_rule() {
+                Rule::number => ast::Comparison {
+                    op: ast::Operator::Equal,
+                    target_value: ast::TargetValue::Single(parse_taget_value(cmp_rule)?),
+                },
+                Rule::comparison => parse_comparison(cmp_rule)?,
+                _ => {
+                    return Err(ParserError::UnreachableRule(pairs.as_str().to_string()));
+                }
+            };
+            let buckets = get_pair(&mut pairs, "buckets")?
+                .as_str()
+                .trim_matches(|x| x == '[' || x == ']')
+                .split(',')
+                .map(|x| x.trim().trim_matches('"').to_string())
+                .collect();
+
+            let timeout = parse_optional_within(&mut pairs)?;
+
+            AssertionKind::Histogram {
+                node_name: name,
+                metric_name,
+                op: cmp.op,
+                target_value: cmp.target_value,
+                buckets,
+                timeout,
+            }
+        }
+        Rule::histogram_quantile => {
+            // Pairs should be in order:
+            // name, metric_name, quantile, cmp, [timeout]
+            let mut pairs = record.into_inner();
+            let name = parse_name(get_pair(&mut pairs, "name")?)?;
+            let metric_name = parse_metric_selector(get_pair(&mut pairs, "metric_name")?)?;
+            let quantile = parse_quantile(get_pair(&mut pairs, "quantile")?)?;
+            let cmp = parse_comparison(get_pair(&mut pairs, "cmp_rule")?)?;
+            let timeout = parse_optional_within(&mut pairs)?;
+
+            AssertionKind::HistogramQuantile {
+                node_name: name,
+                metric_name,
+                quantile,
+                op: cmp.op,
+                target_value: cmp.target_value,
+                timeout,
+            }
+        }
+        Rule::report => {
+            // Pairs should be in order:
+            // name, metric_name | metric_window, cmp, [timeout]
+            let mut pairs = record.into_inner();
+            let name = parse_name(get_pair(&mut pairs, "name")?)?;
+            let metric_pair = get_pair(&mut pairs, "metric_name")?;
+            let (metric_name, window_fn, window) = match metric_pair.as_rule() {
+                Rule::metric_window => {
+                    let (window_fn, metric_name, window) = parse_metric_window(metric_pair)?;
+                    (metric_name, Some(window_fn), Some(window))
+                }
+                _ => (parse_metric_selector(metric_pair)?, None, None),
+            };
+            let cmp = parse_comparison(get_pair(&mut pairs, "cmp_rule")?)?;
+            let timeout = parse_optional_within(&mut pairs)?;
+
+            AssertionKind::Report {
+                node_name: name.to_owned(),
+                metric_name,
+                window_fn,
+                window,
+                op: cmp.op,
+                target_value: cmp.target_value,
+                timeout,
+            }
+        }
+        Rule::log_match => {
+            let capture_pair = record
+                .clone()
+                .into_inner()
+                .find(|pair| pair.as_rule() == Rule::capture_as);
+            let (name, negated, match_type, pattern, timeout) = parse_match_pattern_rule(record)?;
+            let capture = match capture_pair {
+                Some(pair) => Some(parse_capture(pair, negated, &match_type, &pattern, ctx)?),
+                None => None,
+            };
+
+            AssertionKind::LogMatch {
+                node_name: name,
+                negated,
+                match_type,
+                pattern,
+                capture,
+                timeout,
+            }
+        }
+        Rule::count_log_match => {
+            let (name, match_type, pattern, comparison, timeout) =
+                parse_lines_count_match_pattern_rule(record)?;
+
+            AssertionKind::CountLogMatch {
+                node_name: name,
+                match_type,
+                pattern,
+                target_value: comparison.target_value,
+                op: comparison.op,
+                timeout,
+            }
+        }
+        Rule::trace => {
+            // Pairs should be in order:
+            // name, span_id, pattern, [timeout]
+            let mut pairs = record.into_inner();
+            let name = parse_name(get_pair(&mut pairs, "name")?)?;
+            let span_id = get_pair(&mut pairs, "span_id")?.as_str().to_string();
+            let pattern = get_pair(&mut pairs, "pattern")?.as_str().to_string();
+
+            let timeout = parse_optional_within(&mut pairs)?;
+
+            AssertionKind::Trace {
+                node_name: name.to_owned(),
+                span_id,
+                pattern,
+                timeout,
+            }
+        }
+        Rule::system_event => {
+            let (name, negated, match_type, pattern, timeout) = parse_match_pattern_rule(record)?;
+
+            AssertionKind::SystemEvent {
+                node_name: name,
+                negated,
+                match_type,
+                pattern,
+                timeout,
+            }
+        }
+        Rule::custom_js => parse_custom_script_rule(record, ScriptType::Javascript)?,
+        Rule::custom_ts => parse_custom_script_rule(record, ScriptType::Typescript)?,
+        Rule::custom_sh => parse_custom_script_rule(record, ScriptType::Shellscript)?,
+        Rule::sleep => {
+            // Pairs should be in order:
+            // timeout
+            let mut pairs = record.into_inner();
+            let seconds = parse_duration(get_pair(&mut pairs, "duration")?)?;
+
+            AssertionKind::Sleep {
+                seconds: Some(seconds),
+            }
+        }
+        Rule::pause => {
+            // Pairs should be in order:
+            // name
+            let mut pairs = record.into_inner();
+            let name = parse_name(get_pair(&mut pairs, "name")?)?;
+
+            AssertionKind::Pause { node_name: name }
+        }
+        Rule::resume => {
+            // Pairs should be in order:
+            // name
+            let mut pairs = record.into_inner();
+            let name = parse_name(get_pair(&mut pairs, "name")?)?;
 
-                assertions.push(assertion);
+            AssertionKind::Resume { node_name: name }
+        }
+        Rule::restart => {
+            // Pairs should be in order:
+            // name
+            let mut pairs = record.into_inner();
+            let name = parse_name(get_pair(&mut pairs, "name")?)?;
+
+            let after: Option<Duration> = if let Some(after_rule) = pairs.next() {
+                Some(parse_duration(after_rule)?)
+            } else {
+                None
+            };
+
+            AssertionKind::Restart {
+                node_name: name,
+                after,
             }
-            Rule::sleep => {
-                // Pairs should be in order:
-                // timeout
-                let mut pairs = record.into_inner();
-                let seconds = get_pair(&mut pairs, "seconds")?
-                    .as_str()
-                    .parse::<u64>()
-                    .map_err(|_| {
-                        errors::ParserError::ParseError(String::from("Invalid secs value"))
-                    })?;
-
-                let assertion = Assertion {
-                    parsed: AssertionKind::Sleep {
-                        seconds: Some(Duration::from_secs(seconds)),
-                    },
-                    original_line,
-                };
+        }
+        Rule::rpc => {
+            // Pairs should be in order:
+            // name, method, [params], [json_path], value_comparison, [timeout]
+            let mut pairs = record.into_inner();
+            let name = parse_name(get_pair(&mut pairs, "name")?)?;
+            let method = get_pair(&mut pairs, "method")?.as_str().to_string();
+            let mut params = vec![];
+            let mut path = vec![];
+            let mut pair = get_pair(&mut pairs, "value_comparison")?;
+            if pair.as_rule() == Rule::json_array {
+                params = serde_json::from_str(pair.as_str()).map_err(|e| {
+                    ParserError::ParseError(format!("Invalid params {}: {e}", pair.as_str()))
+                })?;
+                pair = get_pair(&mut pairs, "value_comparison")?;
+            }
+            if pair.as_rule() == Rule::json_path {
+                path = pair
+                    .into_inner()
+                    .map(parse_path_segment)
+                    .collect::<Result<Vec<_>, _>>()?;
+                pair = get_pair(&mut pairs, "value_comparison")?;
+            }
+            let comparison = parse_comparison(pair)?;
+            let timeout = parse_optional_within(&mut pairs)?;
+
+            AssertionKind::Rpc {
+                node_name: name,
+                method,
+                params,
+                path,
+                op: comparison.op,
+                target_value: comparison.target_value,
+                timeout,
+            }
+        }
+        Rule::backchannel => {
+            // Pairs should be in order:
+            // key, exists | value_comparison, [timeout]
+            let mut pairs = record.into_inner();
+            let key = get_pair(&mut pairs, "key")?
+                .as_str()
+                .trim_matches('"')
+                .to_string();
+            let cmp_rule = get_pair(&mut pairs, "cmp_rule")?;
+            let cmp = match cmp_rule.as_rule() {
+                Rule::backchannel_exists => None,
+                _ => Some(parse_comparison(cmp_rule)?),
+            };
+            let timeout = parse_optional_within(&mut pairs)?;
+
+            AssertionKind::Backchannel { key, cmp, timeout }
+        }
+        Rule::storage => {
+            // Pairs should be in order:
+            // name, storage_query, comparison, [timeout]
+            let mut pairs = record.into_inner();
+            let name = parse_name(get_pair(&mut pairs, "name")?)?;
+            let mut query_pairs = get_pair(&mut pairs, "storage_query")?.into_inner();
+            let pallet = get_pair(&mut query_pairs, "pallet")?.as_str().to_string();
+            let item = get_pair(&mut query_pairs, "item")?.as_str().to_string();
+            let mut keys = vec![];
+            let mut path = vec![];
+            for pair in query_pairs {
+                match pair.as_rule() {
+                    Rule::storage_keys => {
+                        keys = pair
+                            .into_inner()
+                            .map(parse_arg_value)
+                            .collect::<Result<Vec<_>, _>>()?;
+                    }
+                    _ => path.push(parse_path_segment(pair)?),
+                }
+            }
+            let comparison = parse_comparison(get_pair(&mut pairs, "comparison")?)?;
+            let timeout = parse_optional_within(&mut pairs)?;
+
+            AssertionKind::StorageQuery {
+                node_name: name,
+                pallet,
+                item,
+                keys,
+                path,
+                op: comparison.op,
+                target_value: comparison.target_value,
+                timeout,
+            }
+        }
+        Rule::disconnect => {
+            // Pairs should be in order:
+            // name, peers, [duration]
+            let mut pairs = record.into_inner();
+            let name = parse_name(get_pair(&mut pairs, "name")?)?;
+            let peers = parse_node_selector(get_pair(&mut pairs, "peers")?)?;
+            let duration = parse_chaos_duration(&mut pairs)?;
+
+            AssertionKind::Disconnect {
+                node_name: name,
+                peers,
+                duration,
+            }
+        }
+        Rule::isolate => {
+            // Pairs should be in order:
+            // name, [duration]
+            let mut pairs = record.into_inner();
+            let name = parse_node_selector(get_pair(&mut pairs, "name")?)?;
+            let duration = parse_chaos_duration(&mut pairs)?;
+
+            AssertionKind::Isolate {
+                node_name: name,
+                duration,
+            }
+        }
+        Rule::add_latency => {
+            // Pairs should be in order:
+            // latency, name, [duration]
+            let mut pairs = record.into_inner();
+            let latency = parse_duration(get_pair(&mut pairs, "latency")?)?;
+            let name = parse_node_selector(get_pair(&mut pairs, "name")?)?;
+            let duration = parse_chaos_duration(&mut pairs)?;
+
+            AssertionKind::AddLatency {
+                node_name: name,
+                latency,
+                duration,
+            }
+        }
+        Rule::packet_loss => {
+            // Pairs should be in order:
+            // percentage, name, [duration]
+            let mut pairs = record.into_inner();
+            let loss_percent = parse_percentage(get_pair(&mut pairs, "percentage")?)?;
+            let name = parse_node_selector(get_pair(&mut pairs, "name")?)?;
+            let duration = parse_chaos_duration(&mut pairs)?;
+
+            AssertionKind::PacketLoss {
+                node_name: name,
+                loss_percent,
+                duration,
+            }
+        }
+        Rule::add_node => {
+            // Pairs should be in order:
+            // name, template, [args]
+            let mut pairs = record.into_inner();
+            let name = get_pair(&mut pairs, "name")?.as_str().to_string();
+            let template = get_pair(&mut pairs, "template")?.as_str().to_string();
+            let args = parse_node_args(&mut pairs)?;
+
+            AssertionKind::AddNode {
+                node_name: name,
+                template,
+                args,
+            }
+        }
+        Rule::remove_node => {
+            let name = get_pair(&mut record.into_inner(), "name")?
+                .as_str()
+                .to_string();
 
-                assertions.push(assertion);
+            AssertionKind::RemoveNode { node_name: name }
+        }
+        Rule::upgrade_node => {
+            // Pairs should be in order:
+            // name, artifact, [args], [after]
+            let mut pairs = record.into_inner();
+            let name = parse_name(get_pair(&mut pairs, "name")?)?;
+            let artifact_pair = get_pair(&mut pairs, "artifact")?;
+            let artifact_rule = artifact_pair.as_rule();
+            let location = get_pair(&mut artifact_pair.into_inner(), "location")?
+                .as_str()
+                .to_string();
+            let artifact = match artifact_rule {
+                Rule::upgrade_binary => ast::UpgradeArtifact::Binary(location),
+                _ => ast::UpgradeArtifact::Image(location),
+            };
+            let args = parse_node_args(&mut pairs)?;
+            let after = pairs.next().map(parse_duration).transpose()?;
+
+            AssertionKind::UpgradeNode {
+                node_name: name,
+                artifact,
+                args,
+                after,
             }
-            Rule::pause => {
-                // Pairs should be in order:
-                // name
-                let mut pairs = record.into_inner();
-                let name = parse_name(get_pair(&mut pairs, "name")?)?;
+        }
+        Rule::submit_extrinsic => {
+            // Pairs should be in order:
+            // name, pallet, call, call_args, signer, [wait_for], [timeout]
+            let mut pairs = record.into_inner();
+            let name = parse_name(get_pair(&mut pairs, "name")?)?;
+            let pallet = get_pair(&mut pairs, "pallet")?.as_str().to_string();
+            let call = get_pair(&mut pairs, "call")?.as_str().to_string();
+            let args = get_pair(&mut pairs, "call_args")?
+                .into_inner()
+                .map(parse_arg_value)
+                .collect::<Result<Vec<_>, _>>()?;
+            let signer = get_pair(&mut pairs, "signer")?.as_str().to_string();
+            let wait_for = match pairs.peek().map(|pair| pair.as_rule()) {
+                Some(Rule::inclusion) => Some(ast::WaitFor::Inclusion),
+                Some(Rule::finalization) => Some(ast::WaitFor::Finalization),
+                _ => None,
+            };
+            if wait_for.is_some() {
+                pairs.next();
+            }
+            let timeout = parse_optional_within(&mut pairs)?;
+
+            AssertionKind::SubmitExtrinsic {
+                node_name: name,
+                pallet,
+                call,
+                args,
+                signer,
+                wait_for,
+                timeout,
+            }
+        }
+        Rule::EOI => return check_no_pending_tags(ctx),
+        Rule::comment | Rule::let_binding => return Ok(()),
+        _ => {
+            return Err(errors::ParserError::InvalidRule(record.as_str().to_owned()));
+        }
+    };
 
-                let assertion = Assertion {
-                    parsed: AssertionKind::Pause { node_name: name },
-                    original_line,
-                };
+    check_capture_references(&original_line, &ctx.captures)?;
+    if let AssertionKind::LogMatch {
+        capture: Some(capture),
+        ..
+    } = &parsed
+    {
+        let captures = ctx.parallel_captures.as_mut().unwrap_or(&mut ctx.captures);
+        if !captures.contains(&capture.var) {
+            captures.push(capture.var.clone());
+        }
+    }
+
+    steps.push(Step::Assertion(Assertion {
+        original_line,
+        parsed,
+        timing,
+        test: ctx.test.clone(),
+        section: ctx.section.clone(),
+        tags: merge_tags(&ctx.scope_tags, &line_tags),
+    }));
 
-                assertions.push(assertion);
+    Ok(())
+}
+
+/// Close the open sections (adding them to their parent) until `keep` of them are left
+fn close_sections(open_sections: &mut Vec<Section>, keep: usize, steps: &mut Vec<Step>) {
+    while open_sections.len() > keep {
+        if let Some(closed) = open_sections.pop() {
+            match open_sections.last_mut() {
+                Some(parent) => parent.steps.push(Step::Section(closed)),
+                None => steps.push(Step::Section(closed)),
             }
-            Rule::resume => {
-                // Pairs should be in order:
-                // name
-                let mut pairs = record.into_inner();
-                let name = parse_name(get_pair(&mut pairs, "name")?)?;
+        }
+    }
+}
 
-                let assertion = Assertion {
-                    parsed: AssertionKind::Resume { node_name: name },
-                    original_line,
-                };
+/// Parse a `feature` file and return a `json string`
+pub fn parse(unparsed_file: &str) -> Result<ast::TestDefinition, errors::ParserError> {
+    parse_with_vars(unparsed_file, &HashMap::new())
+}
 
-                assertions.push(assertion);
+/// Parse a `feature` file resolving the `${VAR}` references, the provided
+/// `vars` take precedence over the `let` bindings defined in the file.
+/// Included files are read from the filesystem, relative to the current directory.
+pub fn parse_with_vars(
+    unparsed_file: &str,
+    vars: &HashMap<String, String>,
+) -> Result<ast::TestDefinition, errors::ParserError> {
+    parse_with_loader(unparsed_file, Path::new(""), vars, &|path| {
+        fs::read_to_string(path).map_err(|e| e.to_string())
+    })
+}
+
+/// Parse the `feature` file located at `file_path`, using `loader` to read the
+/// included files (relative to the including one).
+pub fn parse_with_loader(
+    unparsed_file: &str,
+    file_path: &Path,
+    vars: &HashMap<String, String>,
+    loader: &FileLoader,
+) -> Result<ast::TestDefinition, errors::ParserError> {
+    parse_with_env(unparsed_file, file_path, vars, loader, &|name| {
+        std::env::var(name).ok()
+    })
+}
+
+/// Parse the `feature` file like [`parse_with_loader`], using `env` to look up the
+/// environment variables (e.g. when the process environment isn't available, in wasm).
+pub fn parse_with_env(
+    unparsed_file: &str,
+    file_path: &Path,
+    vars: &HashMap<String, String>,
+    loader: &FileLoader,
+    env: &EnvLookup,
+) -> Result<ast::TestDefinition, errors::ParserError> {
+    let mut captures = vec![];
+    declared_captures(
+        unparsed_file,
+        &mut vec![normalize_path(file_path)],
+        vars,
+        &mut captures,
+        &VarSources { loader, env },
+    );
+    let (resolved_file, bindings) = resolve_vars(unparsed_file, vars, &captures, env)?;
+    let mut ctx = ParseContext {
+        vars: scoped_vars(vars, bindings),
+        loader,
+        env,
+        include_chain: vec![normalize_path(file_path)],
+        test: None,
+        section: None,
+        pending_tags: vec![],
+        scope_tags: vec![],
+        previous_record: None,
+        captures: vec![],
+        parallel_captures: None,
+    };
+    let mut pairs = match ZombieNetParser::parse(Rule::file, &resolved_file) {
+        Ok(p) => p,
+        Err(e) => return Err(errors::ParserError::ParseError(e.to_string())),
+    };
+
+    let mut network: Option<String> = None;
+    let mut creds: Option<String> = None;
+    let mut description: Option<String> = None;
+    let mut steps: Vec<Step> = vec![];
+    // headings not closed yet, a `Test:` can only be the first one
+    let mut open_sections: Vec<Section> = vec![];
+
+    let top_level_rule = if let Some(p) = pairs.next() {
+        p
+    } else {
+        return Err(ParserError::Unexpected(String::from(
+            "Invalid top level rule",
+        )));
+    };
+
+    for record in top_level_rule.into_inner() {
+        match record.as_rule() {
+            Rule::description => {
+                description = Some(record.into_inner().as_str().to_owned());
+            }
+            Rule::network => {
+                network = Some(record.into_inner().as_str().to_owned());
             }
-            Rule::restart => {
-                // Pairs should be in order:
-                // name
+            Rule::creds => {
                 let mut pairs = record.into_inner();
-                let name = parse_name(get_pair(&mut pairs, "name")?)?;
-
-                let after: Option<Duration> = if let Some(after_rule) = pairs.next() {
-                    Some(Duration::from_secs(after_rule.as_str().parse().map_err(
-                        |_| ParserError::ParseError(format!("Invalid after value, {after_rule}")),
-                    )?))
+                creds = if let Some(creds_rule) = pairs.next() {
+                    Some(creds_rule.into_inner().as_str().to_owned())
                 } else {
-                    None
-                };
-
-                let assertion = Assertion {
-                    parsed: AssertionKind::Restart {
-                        node_name: name,
-                        after,
-                    },
-                    original_line,
+                    Some(String::from("config"))
                 };
-
-                assertions.push(assertion);
             }
-            Rule::EOI | Rule::comment => (),
+            Rule::test_heading => {
+                close_sections(&mut open_sections, 0, &mut steps);
+                let name = record.into_inner().as_str().trim().to_string();
+                let tags = std::mem::take(&mut ctx.pending_tags);
+                ctx.test = Some(name.clone());
+                ctx.section = None;
+                ctx.scope_tags = tags.clone();
+                open_sections.push(Section {
+                    kind: SectionKind::Test,
+                    name,
+                    tags,
+                    steps: vec![],
+                });
+            }
+            Rule::section_heading => {
+                let in_test =
+                    matches!(open_sections.first(), Some(s) if s.kind == SectionKind::Test);
+                close_sections(&mut open_sections, usize::from(in_test), &mut steps);
+                let name = record.into_inner().as_str().trim().to_string();
+                let tags = std::mem::take(&mut ctx.pending_tags);
+                ctx.section = Some(name.clone());
+                ctx.scope_tags =
+                    merge_tags(open_sections.first().map_or(&[], |test| &test.tags), &tags);
+                open_sections.push(Section {
+                    kind: SectionKind::Section,
+                    name,
+                    tags,
+                    steps: vec![],
+                });
+            }
             _ => {
-                return Err(errors::ParserError::InvalidRule(record.as_str().to_owned()));
+                let current_steps = match open_sections.last_mut() {
+                    Some(section) => &mut section.steps,
+                    None => &mut steps,
+                };
+                parse_body_record(record, &mut ctx, current_steps)?
             }
         }
     }
+    close_sections(&mut open_sections, 0, &mut steps);
+    // the added nodes don't exist before their `add node`
+    let mut missing = vec![];
+    added_nodes(&steps, &mut vec![], &mut missing);
+    check_node_references(&steps, &mut vec![], &mut missing)?;
 
     if network.is_none() {
         return Err(errors::ParserError::MissingFields(String::from(
@@ -683,7 +2050,7 @@ pub fn parse(unparsed_file: &str) -> Result<ast::TestDefinition, errors::ParserE
         description,
         network: network.unwrap(),
         creds,
-        assertions,
+        assertions: steps,
     };
 
     Ok(test_def)
@@ -0,0 +1,59 @@
+//! Helpers to evaluate the metrics assertions.
+
+/// Estimate the `quantile` (between 0 and 1) of a histogram, the same way the
+/// prometheus `histogram_quantile` function does.
+///
+/// `buckets` are the `(upper_bound, cumulative_count)` samples of the `_bucket`
+/// series (i.e. the `le` label and the value), in any order. The samples must
+/// include the `+Inf` bucket and values are interpolated linearly inside the
+/// bucket of the quantile. Returns `None` if there are no observations or the
+/// buckets can't be used (e.g. missing `+Inf` bucket), and `-Inf`/`+Inf` if the
+/// quantile is below 0 or above 1.
+pub fn histogram_quantile(quantile: f64, buckets: &[(f64, f64)]) -> Option<f64> {
+    if quantile.is_nan() {
+        return None;
+    }
+    if quantile < 0.0 {
+        return Some(f64::NEG_INFINITY);
+    }
+    if quantile > 1.0 {
+        return Some(f64::INFINITY);
+    }
+
+    let mut buckets = buckets.to_vec();
+    buckets.sort_by(|a, b| a.0.total_cmp(&b.0));
+    if buckets.len() < 2 || buckets.last()?.0 != f64::INFINITY {
+        return None;
+    }
+
+    // counts scraped while the histogram is updated can decrease between buckets
+    let mut max_count = 0.0_f64;
+    for bucket in buckets.iter_mut() {
+        max_count = max_count.max(bucket.1);
+        bucket.1 = max_count;
+    }
+
+    let observations = buckets.last()?.1;
+    if observations == 0.0 {
+        return None;
+    }
+
+    let mut rank = quantile * observations;
+    let idx = buckets.iter().position(|(_, count)| *count >= rank)?;
+    if idx == buckets.len() - 1 {
+        // the quantile is in the `+Inf` bucket, return the highest finite bound
+        return Some(buckets[idx - 1].0);
+    }
+    if idx == 0 && buckets[0].0 <= 0.0 {
+        return Some(buckets[0].0);
+    }
+
+    let (mut bucket_start, bucket_end, mut count) = (0.0, buckets[idx].0, buckets[idx].1);
+    if idx > 0 {
+        bucket_start = buckets[idx - 1].0;
+        count -= buckets[idx - 1].1;
+        rank -= buckets[idx - 1].1;
+    }
+
+    Some(bucket_start + (bucket_end - bucket_start) * (rank / count))
+}
@@ -3,6 +3,13 @@ use super::*;
 const NETWORK: &str = "Network: ./a.toml";
 const CREDS: &str = "Creds: config";
 
+fn assertion(step: &Step) -> &Assertion {
+    match step {
+        Step::Assertion(assertion) => assertion,
+        _ => panic!("unexpected step {step:?}"),
+    }
+}
+
 #[test]
 fn restart_parse_ok() {
     let line: &str = "alice: restart after 60 seconds";
@@ -152,7 +159,16 @@ fn report_parse_ok() {
                     "fn": "Report",
                     "args": {
                         "node_name": "eve",
-                        "metric_name": "parachain_candidate_dispute_concluded{validity=\"invalid\"}",
+                        "metric_name": {
+                            "name": "parachain_candidate_dispute_concluded",
+                            "matchers": [
+                                {
+                                    "label": "validity",
+                                    "op": "Equal",
+                                    "value": "invalid"
+                                }
+                            ]
+                        },
                         "op": "Equal",
                         "target_value": 0,
                         "timeout": 15
@@ -182,8 +198,15 @@ fn report_parse_calc_ok() {
             "fn": "CalcMetrics",
             "args": {
               "node_name": "alice",
+              "expression": {
+                "BinaryOp": {
+                  "op": "Minus",
+                  "lhs": { "Metric": { "name": "block height", "matchers": [] } },
+                  "rhs": { "Metric": { "name": "finalised block", "matchers": [] } }
+                }
+              },
               "metric_name_a": "block height",
-              "math_ops":  "Minus",
+              "math_ops": "Minus",
               "metric_name_b": "finalised block",
               "op": "IsAtLeast",
               "target_value": 10,
@@ -229,20 +252,20 @@ fn para_dummy_upgrade_parse_ok() {
 #[test]
 fn para_upgrade_parse_ok() {
     let line: &str =
-        r#"alice: parachain 100 perform upgrade with ./some.wasm.compact within 200 seconds"#;
+        r#"alice: parachain 100 perform upgrade with ./some.compact.wasm within 200 seconds"#;
     let data = r#"{
         "description": null,
         "network": "./a.toml",
         "creds": "config",
         "assertions": [
             {
-                "original_line": "alice: parachain 100 perform upgrade with ./some.wasm.compact within 200 seconds",
+                "original_line": "alice: parachain 100 perform upgrade with ./some.compact.wasm within 200 seconds",
                 "parsed": {
                   "fn": "ParaRuntimeUpgrade",
                   "args": {
                     "node_name": "alice",
                     "para_id": 100,
-                    "file_or_uri": "./some.wasm.compact",
+                    "file_or_uri": "./some.compact.wasm",
                     "timeout": 200
                   }
                 }
@@ -787,6 +810,2318 @@ fn no_creds_parse_ok() {
     assert_eq!(result, t);
 }
 
+#[test]
+fn is_up_node_list_parse_ok() {
+    let line: &str = "alice, bob,charlie: is up within 5 secs";
+    let data = r#"{
+        "description": null,
+        "network": "./a.toml",
+        "creds": "config",
+        "assertions": [
+            {
+                "original_line": "alice, bob,charlie: is up within 5 secs",
+                "parsed": {
+                    "fn": "IsUp",
+                    "args": {
+                        "node_name": { "nodes": ["alice", "bob", "charlie"] },
+                        "timeout": 5
+                    }
+                }
+            }
+        ]
+    }"#;
+    let t: TestDefinition = serde_json::from_str(data).unwrap();
+
+    let result = parse(&[NETWORK, CREDS, line].join("\n")).unwrap();
+    assert_eq!(result, t);
+}
+
+#[test]
+fn report_node_glob_parse_ok() {
+    let line: &str = "validator-*: reports node_roles is 4";
+    let data = r#"{
+        "description": null,
+        "network": "./a.toml",
+        "creds": "config",
+        "assertions": [
+            {
+                "original_line": "validator-*: reports node_roles is 4",
+                "parsed": {
+                    "fn": "Report",
+                    "args": {
+                        "node_name": { "glob": "validator-*" },
+                        "metric_name": "node_roles",
+                        "op": "Equal",
+                        "target_value": 4,
+                        "timeout": null
+                    }
+                }
+            }
+        ]
+    }"#;
+    let t: TestDefinition = serde_json::from_str(data).unwrap();
+
+    let result = parse(&[NETWORK, CREDS, line].join("\n")).unwrap();
+    assert_eq!(result, t);
+}
+
+#[test]
+fn pause_node_group_parse_ok() {
+    let line: &str = "all validators: pause";
+    let data = r#"{
+        "description": null,
+        "network": "./a.toml",
+        "creds": "config",
+        "assertions": [
+            {
+                "original_line": "all validators: pause",
+                "parsed": {
+                    "fn": "Pause",
+                    "args": {
+                        "node_name": { "group": "validators" }
+                    }
+                }
+            }
+        ]
+    }"#;
+    let t: TestDefinition = serde_json::from_str(data).unwrap();
+
+    let result = parse(&[NETWORK, CREDS, line].join("\n")).unwrap();
+    assert_eq!(result, t);
+}
+
+#[test]
+fn node_name_starting_with_all_parse_ok() {
+    let result = parse(&[NETWORK, CREDS, "allnodes: is up"].join("\n")).unwrap();
+    assert_eq!(
+        assertion(&result.assertions[0]).parsed,
+        AssertionKind::IsUp {
+            node_name: NodeSelector::Name(String::from("allnodes")),
+            timeout: None,
+        }
+    );
+}
+
+#[test]
+fn report_float_target_parse_ok() {
+    let line: &str = "alice: reports some_ratio is below 0.05 within 15 seconds";
+    let data = r#"{
+        "description": null,
+        "network": "./a.toml",
+        "creds": "config",
+        "assertions": [
+            {
+                "original_line": "alice: reports some_ratio is below 0.05 within 15 seconds",
+                "parsed": {
+                    "fn": "Report",
+                    "args": {
+                        "node_name": "alice",
+                        "metric_name": "some_ratio",
+                        "op": "IsBelow",
+                        "target_value": 0.05,
+                        "timeout": 15
+                    }
+                }
+            }
+        ]
+    }"#;
+    let t: TestDefinition = serde_json::from_str(data).unwrap();
+
+    let result = parse(&[NETWORK, CREDS, line].join("\n")).unwrap();
+    assert_eq!(result, t);
+}
+
+#[test]
+fn report_signed_and_scientific_targets_parse_ok() {
+    let lines = [
+        "alice: reports some_delta is at least -3",
+        "alice: reports polkadot_pvf_execution_time is at most 1.5e-3",
+    ];
+    let result = parse(&[NETWORK, CREDS, lines[0], lines[1]].join("\n")).unwrap();
+
+    let targets: Vec<_> = result
+        .assertions
+        .iter()
+        .map(|step| match &assertion(step).parsed {
+            AssertionKind::Report {
+                op, target_value, ..
+            } => (*op, target_value.clone()),
+            parsed => panic!("unexpected assertion {parsed:?}"),
+        })
+        .collect();
+
+    assert_eq!(
+        targets,
+        vec![
+            (
+                ast::Operator::IsAtLeast,
+                ast::TargetValue::Single(ast::NumericValue::Integer(-3))
+            ),
+            (
+                ast::Operator::IsAtMost,
+                ast::TargetValue::Single(ast::NumericValue::Float(1.5e-3))
+            ),
+        ]
+    );
+}
+
+#[test]
+fn report_numeric_value_roundtrip_ok() {
+    let result = parse(&[NETWORK, CREDS, "alice: reports x is -0.5"].join("\n")).unwrap();
+    let json = serde_json::to_string(&result).unwrap();
+    assert!(json.contains(r#""target_value":-0.5"#));
+
+    let t: TestDefinition = serde_json::from_str(&json).unwrap();
+    assert_eq!(result, t);
+}
+
+#[test]
+fn report_between_range_parse_ok() {
+    let line: &str = "alice: reports peers count is between 5 and 10 within 30 seconds";
+    let data = r#"{
+        "description": null,
+        "network": "./a.toml",
+        "creds": "config",
+        "assertions": [
+            {
+                "original_line": "alice: reports peers count is between 5 and 10 within 30 seconds",
+                "parsed": {
+                    "fn": "Report",
+                    "args": {
+                        "node_name": "alice",
+                        "metric_name": "peers count",
+                        "op": "IsBetween",
+                        "target_value": [5, 10],
+                        "timeout": 30
+                    }
+                }
+            }
+        ]
+    }"#;
+    let t: TestDefinition = serde_json::from_str(data).unwrap();
+
+    let result = parse(&[NETWORK, CREDS, line].join("\n")).unwrap();
+    assert_eq!(result, t);
+}
+
+#[test]
+fn count_log_match_within_range_parse_ok() {
+    let line: &str =
+        r#"alice: count of log lines containing "Imported" is within 0.5..10 within 20 seconds"#;
+    let data = r#"{
+        "description": null,
+        "network": "./a.toml",
+        "creds": "config",
+        "assertions": [
+            {
+                "original_line": "alice: count of log lines containing \"Imported\" is within 0.5..10 within 20 seconds",
+                "parsed": {
+                  "fn": "CountLogMatch",
+                  "args": {
+                    "node_name": "alice",
+                    "match_type": "regex",
+                    "pattern": "Imported",
+                    "op": "IsWithinRange",
+                    "target_value": [0.5, 10],
+                    "timeout": 20
+                  }
+                }
+            }
+        ]
+    }"#;
+    let t: TestDefinition = serde_json::from_str(data).unwrap();
+
+    let result = parse(&[NETWORK, CREDS, line].join("\n")).unwrap();
+    assert_eq!(result, t);
+}
+
+#[test]
+fn custom_js_return_inclusive_range_parse_ok() {
+    let line: &str = "alice: js-script ./0008-custom.js return is within 1..=3 within 200 seconds";
+    let data = r#"{
+        "description": null,
+        "network": "./a.toml",
+        "creds": "config",
+        "assertions": [
+            {
+                "original_line": "alice: js-script ./0008-custom.js return is within 1..=3 within 200 seconds",
+                "parsed": {
+                  "fn": "CustomJs",
+                  "args": {
+                    "node_name": "alice",
+                    "file_path": "./0008-custom.js",
+                    "custom_args": null,
+                    "op": "IsBetween",
+                    "target_value": [1, 3],
+                    "timeout": 200,
+                    "is_ts": false
+                  }
+                }
+            }
+        ]
+    }"#;
+    let t: TestDefinition = serde_json::from_str(data).unwrap();
+
+    let result = parse(&[NETWORK, CREDS, line].join("\n")).unwrap();
+    assert_eq!(result, t);
+}
+
+#[test]
+fn report_range_start_greater_than_end_parse_err() {
+    let result = parse(&[NETWORK, CREDS, "alice: reports x is between 10 and 5"].join("\n"));
+    assert!(result.is_err());
+}
+
+#[test]
+fn is_up_within_minutes_parse_ok() {
+    let line: &str = "alice: is up within 15 minutes";
+    let data = r#"{
+        "description": null,
+        "network": "./a.toml",
+        "creds": "config",
+        "assertions": [
+            {
+                "original_line": "alice: is up within 15 minutes",
+                "parsed": {
+                    "fn": "IsUp",
+                    "args": {
+                        "node_name": "alice",
+                        "timeout": 900
+                    }
+                }
+            }
+        ]
+    }"#;
+    let t: TestDefinition = serde_json::from_str(data).unwrap();
+
+    let result = parse(&[NETWORK, CREDS, line].join("\n")).unwrap();
+    assert_eq!(result, t);
+}
+
+#[test]
+fn sleep_milliseconds_parse_ok() {
+    let line: &str = "sleep 500 ms";
+    let data = r#"{
+        "description": null,
+        "network": "./a.toml",
+        "creds": "config",
+        "assertions": [
+            {
+                "original_line": "sleep 500 ms",
+                "parsed": {
+                    "fn": "Sleep",
+                    "args": {
+                        "seconds": 0.5
+                    }
+                }
+            }
+        ]
+    }"#;
+    let t: TestDefinition = serde_json::from_str(data).unwrap();
+
+    let result = parse(&[NETWORK, CREDS, line].join("\n")).unwrap();
+    assert_eq!(result, t);
+    assert_eq!(
+        assertion(&result.assertions[0]).parsed,
+        AssertionKind::Sleep {
+            seconds: Some(Duration::from_millis(500))
+        }
+    );
+    assert!(serde_json::to_string(&result)
+        .unwrap()
+        .contains(r#""seconds":0.5"#));
+}
+
+#[test]
+fn restart_after_compound_duration_parse_ok() {
+    let lines = [
+        "alice: restart after 1m30s",
+        "bob: restart after 1 hour 2 minutes 3 seconds 4 ms",
+    ];
+    let result = parse(&[NETWORK, CREDS, lines[0], lines[1]].join("\n")).unwrap();
+
+    let durations: Vec<_> = result
+        .assertions
+        .iter()
+        .map(|step| match &assertion(step).parsed {
+            AssertionKind::Restart { after, .. } => *after,
+            parsed => panic!("unexpected assertion {parsed:?}"),
+        })
+        .collect();
+
+    assert_eq!(
+        durations,
+        vec![
+            Some(Duration::from_secs(90)),
+            Some(Duration::from_millis(3_723_004))
+        ]
+    );
+}
+
+#[test]
+fn within_unknown_unit_parse_err() {
+    let result = parse(&[NETWORK, CREDS, "alice: is up within 5 days"].join("\n"));
+    assert!(result.is_err());
+}
+
+#[test]
+fn let_binding_parse_ok() {
+    let lines = r#"
+    let PARA = 2000
+    let NODE = "collator-1"
+    ${NODE}: parachain ${PARA} is registered within 225 seconds
+    "#;
+    let data = r#"{
+        "description": null,
+        "network": "./a.toml",
+        "creds": "config",
+        "assertions": [
+            {
+                "original_line": "collator-1: parachain 2000 is registered within 225 seconds",
+                "parsed": {
+                    "fn": "ParaIsRegistered",
+                    "args": {
+                        "node_name": "collator-1",
+                        "para_id": 2000,
+                        "timeout": 225
+                    }
+                }
+            }
+        ]
+    }"#;
+    let t: TestDefinition = serde_json::from_str(data).unwrap();
+
+    let result = parse(&[NETWORK, CREDS, lines].join("\n")).unwrap();
+    assert_eq!(result, t);
+}
+
+#[test]
+fn let_binding_trailing_comment_parse_ok() {
+    let lines = r#"
+    let THRESHOLD = 5 # blocks
+    let PATTERN = "Imported #12" // quoted
+    let SIGNER = //Alice
+    alice: reports block height is at least ${THRESHOLD} within 10 seconds
+    alice: log line contains "${PATTERN}"
+    alice: log line contains "${SIGNER}"
+    "#;
+    let result = parse(&[NETWORK, CREDS, lines].join("\n")).unwrap();
+
+    assert!(matches!(
+        &assertion(&result.assertions[0]).parsed,
+        AssertionKind::Report {
+            target_value: ast::TargetValue::Single(ast::NumericValue::Integer(5)),
+            timeout: Some(timeout),
+            ..
+        } if *timeout == Duration::from_secs(10)
+    ));
+    let patterns: Vec<_> = result.assertions[1..]
+        .iter()
+        .map(|step| match &assertion(step).parsed {
+            AssertionKind::LogMatch { pattern, .. } => pattern.as_str(),
+            parsed => panic!("unexpected assertion {parsed:?}"),
+        })
+        .collect();
+    assert_eq!(patterns, vec!["Imported #12", "//Alice"]);
+}
+
+#[test]
+fn provided_vars_override_let_binding_parse_ok() {
+    let lines = r#"
+    let THRESHOLD = 10
+    alice: reports block height is at least ${ THRESHOLD }
+    "#;
+    let vars = HashMap::from([(String::from("THRESHOLD"), String::from("20"))]);
+
+    let result = parse_with_vars(&[NETWORK, CREDS, lines].join("\n"), &vars).unwrap();
+    assert_eq!(
+        assertion(&result.assertions[0]).original_line,
+        "alice: reports block height is at least 20"
+    );
+}
+
+#[test]
+fn env_var_parse_ok() {
+    std::env::set_var("ZOMBIENET_PARSER_TEST_NODE", "dave");
+    let result =
+        parse(&[NETWORK, CREDS, "${ZOMBIENET_PARSER_TEST_NODE}: is up"].join("\n")).unwrap();
+    assert_eq!(
+        assertion(&result.assertions[0]).parsed,
+        AssertionKind::IsUp {
+            node_name: NodeSelector::Name(String::from("dave")),
+            timeout: None,
+        }
+    );
+}
+
+#[test]
+fn env_lookup_parse_ok() {
+    let lines = r#"
+    let PARA = 2000
+    ${NODE}: parachain ${PARA} is registered
+    "#;
+    let env = HashMap::from([
+        (String::from("NODE"), String::from("dave")),
+        (String::from("PARA"), String::from("1000")),
+    ]);
+    let result = parse_with_env(
+        &[NETWORK, CREDS, lines].join("\n"),
+        Path::new(""),
+        &HashMap::new(),
+        &|_| Err(String::from("no includes")),
+        &|name| env.get(name).cloned(),
+    )
+    .unwrap();
+
+    // the `let` bindings take precedence over the environment
+    assert_eq!(
+        assertion(&result.assertions[0]).original_line,
+        "dave: parachain 2000 is registered"
+    );
+}
+
+#[test]
+fn template_braces_are_not_vars_parse_err() {
+    // `{{var}}` is left to the templating of the runner (nunjucks)
+    let lines = r#"
+    let NODE = alice
+    {{NODE}}: is up
+    "#;
+    let result = parse(&[NETWORK, CREDS, lines].join("\n"));
+    assert!(matches!(result, Err(ParserError::ParseError(_))));
+}
+
+#[test]
+fn undefined_var_parse_err() {
+    let lines = r#"
+    # ${UNDEFINED} in comments is ignored
+    alice: parachain ${UNDEFINED} is registered
+    "#;
+    let result = parse(&[NETWORK, CREDS, lines].join("\n"));
+    assert!(matches!(
+        result,
+        Err(ParserError::UndefinedVariable(name, 5)) if name == "UNDEFINED"
+    ));
+}
+
+#[test]
+fn var_used_before_let_binding_parse_err() {
+    let lines = r#"
+    alice: parachain ${PARA} is registered
+    let PARA = 2000
+    "#;
+    let result = parse(&[NETWORK, CREDS, lines].join("\n"));
+    assert!(matches!(result, Err(ParserError::UndefinedVariable(_, _))));
+}
+
+#[test]
+fn log_match_capture_parse_ok() {
+    let lines = r#"
+    alice: log line matches "candidate_hash=(?P<hash>0x[0-9a-f]+)" capture as CANDIDATE within 10 seconds
+    bob: log line contains "${CANDIDATE}" within 20 seconds
+    "#;
+    let result = parse(&[NETWORK, CREDS, lines].join("\n")).unwrap();
+    assert_eq!(
+        assertion(&result.assertions[0]).parsed,
+        AssertionKind::LogMatch {
+            node_name: NodeSelector::Name(String::from("alice")),
+            negated: false,
+            match_type: String::from("regex"),
+            pattern: String::from("candidate_hash=(?P<hash>0x[0-9a-f]+)"),
+            capture: Some(ast::Capture {
+                group: String::from("hash"),
+                var: String::from("CANDIDATE"),
+            }),
+            timeout: Some(Duration::from_secs(10)),
+        }
+    );
+    assert!(matches!(
+        &assertion(&result.assertions[1]).parsed,
+        AssertionKind::LogMatch { pattern, capture: None, .. } if pattern == "${CANDIDATE}"
+    ));
+}
+
+#[test]
+fn log_match_capture_named_group_parse_ok() {
+    let line =
+        r#"alice: log line matches "(?<para>\d+) at (?<hash>0x[0-9a-f]+)" capture hash as HASH"#;
+    let result = parse(&[NETWORK, CREDS, line].join("\n")).unwrap();
+    assert!(matches!(
+        &assertion(&result.assertions[0]).parsed,
+        AssertionKind::LogMatch { capture: Some(ast::Capture { group, var }), .. }
+            if group == "hash" && var == "HASH"
+    ));
+}
+
+#[test]
+fn capture_used_before_definition_parse_err() {
+    let lines = r#"
+    bob: log line contains "${CANDIDATE}" within 20 seconds
+    alice: log line matches "candidate_hash=(?P<hash>0x[0-9a-f]+)" capture as CANDIDATE
+    "#;
+    let result = parse(&[NETWORK, CREDS, lines].join("\n"));
+    assert!(matches!(
+        result,
+        Err(ParserError::UndefinedCapture(name, _)) if name == "CANDIDATE"
+    ));
+}
+
+#[test]
+fn capture_in_same_parallel_block_parse_err() {
+    let lines = r#"
+    parallel {
+        alice: log line matches "candidate_hash=(?P<hash>0x[0-9a-f]+)" capture as CANDIDATE
+        bob: log line contains "${CANDIDATE}"
+    }
+    "#;
+    let result = parse(&[NETWORK, CREDS, lines].join("\n"));
+    assert!(matches!(result, Err(ParserError::UndefinedCapture(..))));
+}
+
+#[test]
+fn capture_without_named_group_parse_err() {
+    let line = r#"alice: log line matches "candidate_hash=(0x[0-9a-f]+)" capture as CANDIDATE"#;
+    let result = parse(&[NETWORK, CREDS, line].join("\n"));
+    assert!(matches!(result, Err(ParserError::ParseError(msg)) if msg.contains("named group")));
+}
+
+#[test]
+fn capture_from_glob_or_negated_match_parse_err() {
+    for line in [
+        r#"alice: log line matches glob "*(?P<hash>0x*)" capture as CANDIDATE"#,
+        r#"alice: log line does not match "(?P<hash>0x[0-9a-f]+)" capture as CANDIDATE"#,
+    ] {
+        let result = parse(&[NETWORK, CREDS, line].join("\n"));
+        assert!(
+            matches!(result, Err(ParserError::ParseError(msg)) if msg.contains("need a (not negated) regex"))
+        );
+    }
+}
+
+fn memory_loader(
+    files: Vec<(&'static str, &'static str)>,
+) -> impl Fn(&Path) -> Result<String, String> {
+    let files: HashMap<PathBuf, String> = files
+        .into_iter()
+        .map(|(path, content)| (PathBuf::from(path), content.to_string()))
+        .collect();
+    move |path| {
+        files
+            .get(path)
+            .cloned()
+            .ok_or_else(|| format!("{} not found", path.display()))
+    }
+}
+
+#[test]
+fn include_parse_ok() {
+    let loader = memory_loader(vec![
+        (
+            "tests/common/base.zndsl",
+            "let PARA = 100\nalice: is up\nInclude: ../para.zndsl",
+        ),
+        ("tests/para.zndsl", "alice: parachain ${PARA} is registered"),
+    ]);
+    let lines = r#"
+    Include: ./common/base.zndsl
+    bob: is up
+    "#;
+    let data = r#"{
+        "description": null,
+        "network": "./a.toml",
+        "creds": "config",
+        "assertions": [
+            {
+                "original_line": "alice: is up",
+                "parsed": {
+                    "fn": "IsUp",
+                    "args": {
+                        "node_name": "alice",
+                        "timeout": null
+                    }
+                }
+            },
+            {
+                "original_line": "alice: parachain 100 is registered",
+                "parsed": {
+                    "fn": "ParaIsRegistered",
+                    "args": {
+                        "node_name": "alice",
+                        "para_id": 100,
+                        "timeout": null
+                    }
+                }
+            },
+            {
+                "original_line": "bob: is up",
+                "parsed": {
+                    "fn": "IsUp",
+                    "args": {
+                        "node_name": "bob",
+                        "timeout": null
+                    }
+                }
+            }
+        ]
+    }"#;
+    let t: TestDefinition = serde_json::from_str(data).unwrap();
+
+    let result = parse_with_loader(
+        &[NETWORK, CREDS, lines].join("\n"),
+        Path::new("tests/0001-test.zndsl"),
+        &HashMap::new(),
+        &loader,
+    )
+    .unwrap();
+    assert_eq!(result, t);
+}
+
+#[test]
+fn capture_in_quoted_string_is_not_declared_parse_ok() {
+    let lines = r#"
+    alice: log line contains "capture as HOME"
+    bob: log line contains "${HOME}"
+    "#;
+    let env = HashMap::from([(String::from("HOME"), String::from("/root"))]);
+    let result = parse_with_env(
+        &[NETWORK, CREDS, lines].join("\n"),
+        Path::new(""),
+        &HashMap::new(),
+        &|_| Err(String::from("no includes")),
+        &|name| env.get(name).cloned(),
+    )
+    .unwrap();
+    assert!(matches!(
+        &assertion(&result.assertions[1]).parsed,
+        AssertionKind::LogMatch { pattern, .. } if pattern == "/root"
+    ));
+}
+
+#[test]
+fn capture_from_include_parse_ok() {
+    let loader = memory_loader(vec![(
+        "tests/frag.zndsl",
+        r#"alice: log line matches "candidate_hash=(?P<hash>0x[0-9a-f]+)" capture as CANDIDATE"#,
+    )]);
+    let lines = r#"
+    Include: ./frag.zndsl
+    bob: log line contains "${CANDIDATE}"
+    "#;
+    let result = parse_with_loader(
+        &[NETWORK, CREDS, lines].join("\n"),
+        Path::new("tests/0001-test.zndsl"),
+        &HashMap::new(),
+        &loader,
+    )
+    .unwrap();
+    assert!(matches!(
+        &assertion(&result.assertions[1]).parsed,
+        AssertionKind::LogMatch { pattern, .. } if pattern == "${CANDIDATE}"
+    ));
+
+    let used_before_include = r#"
+    bob: log line contains "${CANDIDATE}"
+    Include: ./frag.zndsl
+    "#;
+    let result = parse_with_loader(
+        &[NETWORK, CREDS, used_before_include].join("\n"),
+        Path::new("tests/0001-test.zndsl"),
+        &HashMap::new(),
+        &loader,
+    );
+    assert!(matches!(result, Err(ParserError::UndefinedCapture(..))));
+}
+
+#[test]
+fn include_cycle_parse_err() {
+    let loader = memory_loader(vec![
+        ("a.zndsl", "Include: ./b.zndsl"),
+        ("b.zndsl", "alice: is up\nInclude: ./a.zndsl"),
+    ]);
+    let result = parse_with_loader(
+        &[NETWORK, CREDS, "Include: ./a.zndsl"].join("\n"),
+        Path::new("0001-test.zndsl"),
+        &HashMap::new(),
+        &loader,
+    );
+
+    assert_eq!(
+        result.unwrap_err().to_string(),
+        "Include cycle detected: 0001-test.zndsl -> a.zndsl -> b.zndsl -> a.zndsl"
+    );
+}
+
+#[test]
+fn include_error_reports_chain_parse_err() {
+    let loader = memory_loader(vec![
+        ("a.zndsl", "Include: ./b.zndsl"),
+        ("b.zndsl", "alice: is upp"),
+    ]);
+    let result = parse_with_loader(
+        &[NETWORK, CREDS, "Include: ./a.zndsl"].join("\n"),
+        Path::new("0001-test.zndsl"),
+        &HashMap::new(),
+        &loader,
+    );
+
+    match result {
+        Err(ParserError::Include(chain, e)) => {
+            assert_eq!(chain, "0001-test.zndsl -> a.zndsl -> b.zndsl");
+            assert!(matches!(*e, ParserError::ParseError(_)));
+        }
+        _ => panic!("unexpected result {result:?}"),
+    }
+}
+
+#[test]
+fn include_missing_file_parse_err() {
+    let loader = memory_loader(vec![]);
+    let result = parse_with_loader(
+        &[NETWORK, CREDS, "Include: ./missing.zndsl"].join("\n"),
+        Path::new("0001-test.zndsl"),
+        &HashMap::new(),
+        &loader,
+    );
+
+    match result {
+        Err(ParserError::Include(chain, e)) => {
+            assert_eq!(chain, "0001-test.zndsl -> missing.zndsl");
+            assert!(matches!(*e, ParserError::IncludeLoad(..)));
+        }
+        _ => panic!("unexpected result {result:?}"),
+    }
+}
+
+#[test]
+fn parallel_block_parse_ok() {
+    let lines = r#"
+    alice: is up
+    parallel {
+        alice: parachain 100 is registered within 225 seconds
+        # parachains onboarding
+        alice: parachain 101 is registered within 225 seconds
+    }
+    alice: pause
+    "#;
+    let data = r#"{
+        "description": null,
+        "network": "./a.toml",
+        "creds": "config",
+        "assertions": [
+            {
+                "original_line": "alice: is up",
+                "parsed": {
+                    "fn": "IsUp",
+                    "args": {
+                        "node_name": "alice",
+                        "timeout": null
+                    }
+                }
+            },
+            {
+                "parallel": [
+                    {
+                        "original_line": "alice: parachain 100 is registered within 225 seconds",
+                        "parsed": {
+                            "fn": "ParaIsRegistered",
+                            "args": {
+                                "node_name": "alice",
+                                "para_id": 100,
+                                "timeout": 225
+                            }
+                        }
+                    },
+                    {
+                        "original_line": "alice: parachain 101 is registered within 225 seconds",
+                        "parsed": {
+                            "fn": "ParaIsRegistered",
+                            "args": {
+                                "node_name": "alice",
+                                "para_id": 101,
+                                "timeout": 225
+                            }
+                        }
+                    }
+                ]
+            },
+            {
+                "original_line": "alice: pause",
+                "parsed": {
+                    "fn": "Pause",
+                    "args": {
+                        "node_name": "alice"
+                    }
+                }
+            }
+        ]
+    }"#;
+    let t: TestDefinition = serde_json::from_str(data).unwrap();
+
+    let result = parse(&[NETWORK, CREDS, lines].join("\n")).unwrap();
+    assert_eq!(result, t);
+}
+
+#[test]
+fn parallel_block_with_command_parse_err() {
+    let lines = r#"
+    concurrently {
+        alice: is up
+        bob: restart after 5 seconds
+    }
+    "#;
+    let result = parse(&[NETWORK, CREDS, lines].join("\n"));
+    assert!(matches!(result, Err(ParserError::InvalidParallelBlock(_))));
+}
+
+#[test]
+fn nested_parallel_block_parse_err() {
+    let lines = r#"
+    parallel {
+        alice: is up
+        parallel {
+            bob: is up
+        }
+    }
+    "#;
+    let result = parse(&[NETWORK, CREDS, lines].join("\n"));
+    assert!(matches!(result, Err(ParserError::InvalidParallelBlock(_))));
+}
+
+#[test]
+fn retry_policy_parse_ok() {
+    let line: &str =
+        "alice: parachain 100 is registered within 225 seconds retry 3 times every 5 seconds";
+    let data = r#"{
+        "description": null,
+        "network": "./a.toml",
+        "creds": "config",
+        "assertions": [
+            {
+                "original_line": "alice: parachain 100 is registered within 225 seconds retry 3 times every 5 seconds",
+                "parsed": {
+                    "fn": "ParaIsRegistered",
+                    "args": {
+                        "node_name": "alice",
+                        "para_id": 100,
+                        "timeout": 225
+                    }
+                },
+                "timing": {
+                    "retries": 3,
+                    "interval": 5
+                }
+            }
+        ]
+    }"#;
+    let t: TestDefinition = serde_json::from_str(data).unwrap();
+
+    let result = parse(&[NETWORK, CREDS, line].join("\n")).unwrap();
+    assert_eq!(result, t);
+}
+
+#[test]
+fn polling_policy_parse_ok() {
+    let lines = r#"
+    alice: log line contains "Imported #12" polling every 2 seconds
+    alice: js-script ./0008-custom.js return is 1 within 200 seconds polling every 500 ms
+    alice: is up retry 1 time
+    "#;
+    let result = parse(&[NETWORK, CREDS, lines].join("\n")).unwrap();
+
+    let timings: Vec<_> = result
+        .assertions
+        .iter()
+        .map(|step| assertion(step).timing.clone())
+        .collect();
+    assert_eq!(
+        timings,
+        vec![
+            Some(ast::TimingPolicy {
+                retries: None,
+                interval: Some(Duration::from_secs(2)),
+            }),
+            Some(ast::TimingPolicy {
+                retries: None,
+                interval: Some(Duration::from_millis(500)),
+            }),
+            Some(ast::TimingPolicy {
+                retries: Some(1),
+                interval: None,
+            }),
+        ]
+    );
+    assert!(matches!(
+        assertion(&result.assertions[0]).parsed,
+        AssertionKind::LogMatch { timeout: None, .. }
+    ));
+}
+
+#[test]
+fn timing_policy_on_command_parse_err() {
+    let result = parse(&[NETWORK, CREDS, "alice: pause retry 3 times"].join("\n"));
+    assert!(result.is_err());
+}
+
+#[test]
+fn timing_policy_zero_interval_parse_err() {
+    for line in [
+        "alice: is up polling every 0 seconds",
+        "alice: is up retry 0 times every 0 seconds",
+    ] {
+        let result = parse(&[NETWORK, CREDS, line].join("\n"));
+        assert!(
+            matches!(result, Err(ParserError::ParseError(msg)) if msg.contains("greater than zero"))
+        );
+    }
+}
+
+#[test]
+fn log_match_negated_parse_ok() {
+    let line: &str = r#"alice: log line does not contain "panicked" within 60 seconds"#;
+    let data = r#"{
+        "description": null,
+        "network": "./a.toml",
+        "creds": "config",
+        "assertions": [
+            {
+                "original_line": "alice: log line does not contain \"panicked\" within 60 seconds",
+                "parsed": {
+                  "fn": "LogMatch",
+                  "args": {
+                    "node_name": "alice",
+                    "negated": true,
+                    "match_type": "regex",
+                    "pattern": "panicked",
+                    "timeout": 60
+                  }
+                }
+            }
+        ]
+    }"#;
+    let t: TestDefinition = serde_json::from_str(data).unwrap();
+
+    let result = parse(&[NETWORK, CREDS, line].join("\n")).unwrap();
+    assert_eq!(result, t);
+}
+
+#[test]
+fn system_event_negated_parse_ok() {
+    let line: &str = r#"alice: system event does not match glob "*Dispute*" within 30 seconds"#;
+    let result = parse(&[NETWORK, CREDS, line].join("\n")).unwrap();
+    assert_eq!(
+        assertion(&result.assertions[0]).parsed,
+        AssertionKind::SystemEvent {
+            node_name: NodeSelector::Name(String::from("alice")),
+            negated: true,
+            match_type: String::from("glob"),
+            pattern: String::from("*Dispute*"),
+            timeout: Some(Duration::from_secs(30)),
+        }
+    );
+}
+
+#[test]
+fn para_is_not_registered_parse_ok() {
+    let line: &str = "alice: parachain 100 is not registered within 30 seconds";
+    let data = r#"{
+        "description": null,
+        "network": "./a.toml",
+        "creds": "config",
+        "assertions": [
+            {
+                "original_line": "alice: parachain 100 is not registered within 30 seconds",
+                "parsed": {
+                    "fn": "ParaIsRegistered",
+                    "args": {
+                        "node_name": "alice",
+                        "para_id": 100,
+                        "negated": true,
+                        "timeout": 30
+                    }
+                }
+            }
+        ]
+    }"#;
+    let t: TestDefinition = serde_json::from_str(data).unwrap();
+
+    let result = parse(&[NETWORK, CREDS, line].join("\n")).unwrap();
+    assert_eq!(result, t);
+}
+
+#[test]
+fn test_and_sections_parse_ok() {
+    let lines = r#"
+    alice: is up
+    Test: parachain onboarding
+    alice: parachain 100 is registered
+    Section: upgrades
+    alice: parachain 100 perform dummy upgrade
+    Test: disputes
+    bob: is up
+    "#;
+    let data = r#"{
+        "description": null,
+        "network": "./a.toml",
+        "creds": "config",
+        "assertions": [
+            {
+                "original_line": "alice: is up",
+                "parsed": {
+                    "fn": "IsUp",
+                    "args": {
+                        "node_name": "alice",
+                        "timeout": null
+                    }
+                }
+            },
+            {
+                "kind": "Test",
+                "name": "parachain onboarding",
+                "steps": [
+                    {
+                        "original_line": "alice: parachain 100 is registered",
+                        "parsed": {
+                            "fn": "ParaIsRegistered",
+                            "args": {
+                                "node_name": "alice",
+                                "para_id": 100,
+                                "timeout": null
+                            }
+                        },
+                        "test": "parachain onboarding"
+                    },
+                    {
+                        "kind": "Section",
+                        "name": "upgrades",
+                        "steps": [
+                            {
+                                "original_line": "alice: parachain 100 perform dummy upgrade",
+                                "parsed": {
+                                    "fn": "ParaRuntimeDummyUpgrade",
+                                    "args": {
+                                        "node_name": "alice",
+                                        "para_id": 100,
+                                        "timeout": null
+                                    }
+                                },
+                                "test": "parachain onboarding",
+                                "section": "upgrades"
+                            }
+                        ]
+                    }
+                ]
+            },
+            {
+                "kind": "Test",
+                "name": "disputes",
+                "steps": [
+                    {
+                        "original_line": "bob: is up",
+                        "parsed": {
+                            "fn": "IsUp",
+                            "args": {
+                                "node_name": "bob",
+                                "timeout": null
+                            }
+                        },
+                        "test": "disputes"
+                    }
+                ]
+            }
+        ]
+    }"#;
+    let t: TestDefinition = serde_json::from_str(data).unwrap();
+
+    let result = parse(&[NETWORK, CREDS, lines].join("\n")).unwrap();
+    assert_eq!(result, t);
+}
+
+#[test]
+fn sections_without_test_parse_ok() {
+    let lines = r#"
+    Section: setup
+    alice: is up
+    Section: checks
+    alice: reports block height is at least 10
+    "#;
+    let result = parse(&[NETWORK, CREDS, lines].join("\n")).unwrap();
+
+    let sections: Vec<_> = result
+        .assertions
+        .iter()
+        .map(|step| match step {
+            Step::Section(section) => {
+                let assertion = assertion(&section.steps[0]);
+                (
+                    section.kind,
+                    section.name.as_str(),
+                    assertion.test.clone(),
+                    assertion.section.clone(),
+                )
+            }
+            _ => panic!("unexpected step {step:?}"),
+        })
+        .collect();
+    assert_eq!(
+        sections,
+        vec![
+            (
+                ast::SectionKind::Section,
+                "setup",
+                None,
+                Some(String::from("setup"))
+            ),
+            (
+                ast::SectionKind::Section,
+                "checks",
+                None,
+                Some(String::from("checks"))
+            ),
+        ]
+    );
+}
+
+#[test]
+fn tags_parse_ok() {
+    let lines = r#"
+    @smoke alice: is up
+    @slow
+    Test: upgrades
+    @metrics @smoke
+    alice: reports block height is at least 10
+    @fast parallel {
+        @logs bob: log line contains "Imported #12"
+        bob: is up
+    }
+    "#;
+    let result = parse(&[NETWORK, CREDS, lines].join("\n")).unwrap();
+
+    let tags = |step: &Step| assertion(step).tags.clone();
+    assert_eq!(tags(&result.assertions[0]), vec!["smoke"]);
+    let Step::Section(section) = &result.assertions[1] else {
+        panic!("unexpected step {:?}", result.assertions[1]);
+    };
+    assert_eq!(section.tags, vec!["slow"]);
+    assert_eq!(tags(&section.steps[0]), vec!["slow", "metrics", "smoke"]);
+    let Step::Parallel { parallel } = &section.steps[1] else {
+        panic!("unexpected step {:?}", section.steps[1]);
+    };
+    assert_eq!(tags(&parallel[0]), vec!["slow", "fast", "logs"]);
+    assert_eq!(tags(&parallel[1]), vec!["slow", "fast"]);
+}
+
+#[test]
+fn suffix_tags_parse_ok() {
+    let lines = r#"
+    bob: is up @x @y
+    Section: other
+    @z alice: is up @x
+    "#;
+    let result = parse(&[NETWORK, CREDS, lines].join("\n")).unwrap();
+
+    assert_eq!(assertion(&result.assertions[0]).tags, vec!["x", "y"]);
+    let Step::Section(section) = &result.assertions[1] else {
+        panic!("unexpected step {:?}", result.assertions[1]);
+    };
+    assert!(section.tags.is_empty());
+    assert_eq!(assertion(&section.steps[0]).tags, vec!["z", "x"]);
+}
+
+#[test]
+fn suffix_tags_after_block_parse_err() {
+    let lines = r#"
+    parallel {
+        bob: is up
+    } @x
+    alice: is up
+    "#;
+    let result = parse(&[NETWORK, CREDS, lines].join("\n"));
+    assert!(
+        matches!(result, Err(ParserError::ParseError(msg)) if msg.contains("start of the line"))
+    );
+}
+
+#[test]
+fn dangling_tags_parse_err() {
+    let line: &str = r#"alice: is up
+    @smoke"#;
+    let result = parse(&[NETWORK, CREDS, line].join("\n"));
+    assert!(matches!(result, Err(ParserError::ParseError(_))));
+}
+
+#[test]
+fn filter_by_tags_ok() {
+    let lines = r#"
+    @smoke alice: is up
+    @slow bob: pause
+    @slow bob: is up
+    @smoke bob: resume
+    @flaky alice: pause
+    alice: resume
+    Section: metrics
+    @slow alice: reports block height is at least 10
+    "#;
+    let result = parse(&[NETWORK, CREDS, lines].join("\n")).unwrap();
+
+    let lines_of = |test_def: &TestDefinition| -> Vec<String> {
+        test_def
+            .assertions
+            .iter()
+            .map(|step| assertion(step).original_line.clone())
+            .collect()
+    };
+    let filtered = filter_by_tags(result, &["@smoke".into()], &["flaky".into()]);
+    assert_eq!(
+        lines_of(&filtered),
+        vec!["alice: is up", "bob: pause", "bob: resume"]
+    );
+}
+
+#[test]
+fn filter_by_tags_pause_lists_ok() {
+    let lines = r#"
+    @c alice,bob: pause
+    charlie: pause
+    alice: resume
+    bob,charlie: resume
+    "#;
+    let result = parse(&[NETWORK, CREDS, lines].join("\n")).unwrap();
+
+    let filtered = filter_by_tags(result, &[], &["c".into()]);
+    let parsed: Vec<_> = filtered
+        .assertions
+        .iter()
+        .map(|step| &assertion(step).parsed)
+        .collect();
+    assert_eq!(
+        parsed,
+        vec![
+            &AssertionKind::Pause {
+                node_name: NodeSelector::Name("charlie".into())
+            },
+            &AssertionKind::Resume {
+                node_name: NodeSelector::Name("charlie".into())
+            },
+        ]
+    );
+}
+
+#[test]
+fn filter_by_tags_added_nodes_ok() {
+    let lines = r#"
+    @scale add node ferdie from template bob
+    ferdie: is up
+    alice,ferdie: pause
+    alice: resume
+    @scale remove node bob
+    add node bob from template alice
+    bob: is up
+    "#;
+    let result = parse(&[NETWORK, CREDS, lines].join("\n")).unwrap();
+
+    let filtered = filter_by_tags(result, &[], &["scale".into()]);
+    let lines: Vec<_> = filtered
+        .assertions
+        .iter()
+        .map(|step| assertion(step).original_line.as_str())
+        .collect();
+    assert_eq!(lines, vec!["bob: is up"]);
+}
+
+#[test]
+fn filter_by_tags_captures_ok() {
+    let lines = r#"
+    @slow alice: log line matches "h=(?P<h>0x[0-9a-f]+)" capture as C
+    bob: log line contains "${C}"
+    bob: log line matches "${C} in (?P<block>\d+)" capture as BLOCK
+    charlie: log line contains "block ${BLOCK}"
+    charlie: is up
+    "#;
+    let result = parse(&[NETWORK, CREDS, lines].join("\n")).unwrap();
+
+    let filtered = filter_by_tags(result, &[], &["slow".into()]);
+    let lines: Vec<_> = filtered
+        .assertions
+        .iter()
+        .map(|step| assertion(step).original_line.as_str())
+        .collect();
+    assert_eq!(lines, vec!["charlie: is up"]);
+}
+
+#[test]
+fn report_metric_label_matchers_parse_ok() {
+    let line: &str = r#"alice: reports polkadot_parachain_candidate_dispute_votes{validity="valid", origin!="local",peer=~"12D3.*",kind!~"a|b"} is at least 1"#;
+    let result = parse(&[NETWORK, CREDS, line].join("\n")).unwrap();
+
+    let AssertionKind::Report { metric_name, .. } = &assertion(&result.assertions[0]).parsed else {
+        panic!("unexpected assertion {:?}", result.assertions[0]);
+    };
+    let matcher = |label: &str, op, value: &str| ast::LabelMatcher {
+        label: label.into(),
+        op,
+        value: value.into(),
+    };
+    assert_eq!(
+        metric_name,
+        &ast::MetricSelector {
+            name: "polkadot_parachain_candidate_dispute_votes".into(),
+            matchers: vec![
+                matcher("validity", ast::LabelOp::Equal, "valid"),
+                matcher("origin", ast::LabelOp::NotEqual, "local"),
+                matcher("peer", ast::LabelOp::RegexMatch, "12D3.*"),
+                matcher("kind", ast::LabelOp::RegexNotMatch, "a|b"),
+            ],
+        }
+    );
+}
+
+#[test]
+fn report_metric_selector_serialization_ok() {
+    let lines = [
+        "alice: reports block height is at least 10",
+        r#"alice: reports dispute_votes{validity="valid"} is 0"#,
+    ];
+    let result = parse(&[NETWORK, CREDS, lines[0], lines[1]].join("\n")).unwrap();
+    let json = serde_json::to_value(&result.assertions).unwrap();
+
+    // without matchers the metric is a plain string, as before the matchers
+    assert_eq!(
+        json[0]["parsed"]["args"]["metric_name"],
+        serde_json::json!("block height")
+    );
+    assert_eq!(
+        json[1]["parsed"]["args"]["metric_name"],
+        serde_json::json!({
+            "name": "dispute_votes",
+            "matchers": [{ "label": "validity", "op": "Equal", "value": "valid" }]
+        })
+    );
+
+    let t: TestDefinition = serde_json::from_str(&serde_json::to_string(&result).unwrap()).unwrap();
+    assert_eq!(result, t);
+}
+
+#[test]
+fn report_metric_invalid_regex_parse_err() {
+    let line: &str = r#"alice: reports dispute_votes{origin=~"(remote"} is 1"#;
+    let result = parse(&[NETWORK, CREDS, line].join("\n"));
+    assert!(matches!(result, Err(ParserError::InvalidRegex(..))));
+}
+
+#[test]
+fn report_rate_over_window_parse_ok() {
+    let line: &str = r#"alice: reports rate of substrate_block_height over 60 seconds is at least 0.15 within 200 seconds"#;
+    let data = r#"{
+        "description": null,
+        "network": "./a.toml",
+        "creds": "config",
+        "assertions": [
+            {
+                "original_line": "alice: reports rate of substrate_block_height over 60 seconds is at least 0.15 within 200 seconds",
+                "parsed": {
+                    "fn": "Report",
+                    "args": {
+                        "node_name": "alice",
+                        "metric_name": "substrate_block_height",
+                        "window_fn": "Rate",
+                        "window": 60,
+                        "op": "IsAtLeast",
+                        "target_value": 0.15,
+                        "timeout": 200
+                    }
+                }
+            }
+        ]
+    }"#;
+    let t: TestDefinition = serde_json::from_str(data).unwrap();
+
+    let result = parse(&[NETWORK, CREDS, line].join("\n")).unwrap();
+    assert_eq!(result, t);
+}
+
+#[test]
+fn report_increase_over_window_parse_ok() {
+    let line: &str =
+        r#"alice: reports increase of dispute_votes{validity="valid"} over 2 minutes is above 3"#;
+    let result = parse(&[NETWORK, CREDS, line].join("\n")).unwrap();
+
+    let AssertionKind::Report {
+        metric_name,
+        window_fn,
+        window,
+        ..
+    } = &assertion(&result.assertions[0]).parsed
+    else {
+        panic!("unexpected assertion {:?}", result.assertions[0]);
+    };
+    assert_eq!(metric_name.name, "dispute_votes");
+    assert_eq!(*window_fn, Some(ast::WindowFn::Increase));
+    assert_eq!(*window, Some(Duration::from_secs(120)));
+}
+
+#[test]
+fn report_zero_window_parse_err() {
+    let line: &str = r#"alice: reports rate of substrate_block_height over 0 seconds is 1"#;
+    let result = parse(&[NETWORK, CREDS, line].join("\n"));
+    assert!(matches!(result, Err(ParserError::ParseError(_))));
+}
+
+#[test]
+fn report_calc_expression_parse_ok() {
+    let line: &str =
+        r#"alice: reports (block height - finalised block) / 2 + peers * 1.5 is below 10"#;
+    let result = parse(&[NETWORK, CREDS, line].join("\n")).unwrap();
+
+    let AssertionKind::CalcMetrics {
+        expression,
+        operands,
+        ..
+    } = &assertion(&result.assertions[0]).parsed
+    else {
+        panic!("unexpected assertion {:?}", result.assertions[0]);
+    };
+    let metric = |name: &str| {
+        Box::new(ast::MetricExpr::Metric(ast::MetricSelector {
+            name: name.into(),
+            matchers: vec![],
+        }))
+    };
+    let constant = |value| Box::new(ast::MetricExpr::Constant(value));
+    let binary = |op, lhs, rhs| Box::new(ast::MetricExpr::BinaryOp { op, lhs, rhs });
+    let expected = binary(
+        ast::MathOps::Plus,
+        binary(
+            ast::MathOps::Divide,
+            binary(
+                ast::MathOps::Minus,
+                metric("block height"),
+                metric("finalised block"),
+            ),
+            constant(ast::NumericValue::Integer(2)),
+        ),
+        binary(
+            ast::MathOps::Multiply,
+            metric("peers"),
+            constant(ast::NumericValue::Float(1.5)),
+        ),
+    );
+    assert_eq!(expression, expected.as_ref());
+    // only the two metrics form has the operands of the previous format
+    assert_eq!(*operands, None);
+}
+
+#[test]
+fn report_calc_left_associative_parse_ok() {
+    let line: &str = r#"alice: reports a - b - c is 0"#;
+    let result = parse(&[NETWORK, CREDS, line].join("\n")).unwrap();
+
+    let AssertionKind::CalcMetrics { expression, .. } = &assertion(&result.assertions[0]).parsed
+    else {
+        panic!("unexpected assertion {:?}", result.assertions[0]);
+    };
+    let ast::MetricExpr::BinaryOp { lhs, rhs, .. } = expression else {
+        panic!("unexpected expression {expression:?}");
+    };
+    assert!(matches!(lhs.as_ref(), ast::MetricExpr::BinaryOp { .. }));
+    assert!(matches!(rhs.as_ref(), ast::MetricExpr::Metric(m) if m.name == "c"));
+}
+
+#[test]
+fn report_calc_division_by_zero_parse_err() {
+    let line: &str = r#"alice: reports block height / 0 is 1"#;
+    let result = parse(&[NETWORK, CREDS, line].join("\n"));
+    assert!(matches!(result, Err(ParserError::ParseError(_))));
+}
+
+#[test]
+fn histogram_quantile_parse_ok() {
+    let line: &str =
+        r#"alice: reports histogram polkadot_pvf_execution_time p95 is below 2 within 60 seconds"#;
+    let data = r#"{
+        "description": null,
+        "network": "./a.toml",
+        "creds": "config",
+        "assertions": [
+            {
+                "original_line": "alice: reports histogram polkadot_pvf_execution_time p95 is below 2 within 60 seconds",
+                "parsed": {
+                    "fn": "HistogramQuantile",
+                    "args": {
+                        "node_name": "alice",
+                        "metric_name": "polkadot_pvf_execution_time",
+                        "quantile": 0.95,
+                        "op": "IsBelow",
+                        "target_value": 2,
+                        "timeout": 60
+                    }
+                }
+            }
+        ]
+    }"#;
+    let t: TestDefinition = serde_json::from_str(data).unwrap();
+
+    let result = parse(&[NETWORK, CREDS, line].join("\n")).unwrap();
+    assert_eq!(result, t);
+}
+
+#[test]
+fn histogram_quantile_keyword_parse_ok() {
+    let line: &str =
+        r#"alice: reports histogram polkadot_pvf_execution_time quantile 0.99 is at most 1.5"#;
+    let result = parse(&[NETWORK, CREDS, line].join("\n")).unwrap();
+
+    let AssertionKind::HistogramQuantile { quantile, .. } =
+        &assertion(&result.assertions[0]).parsed
+    else {
+        panic!("unexpected assertion {:?}", result.assertions[0]);
+    };
+    assert_eq!(*quantile, ast::NumericValue::Float(0.99));
+}
+
+#[test]
+fn histogram_quantile_out_of_range_parse_err() {
+    let line: &str = r#"alice: reports histogram polkadot_pvf_execution_time p101 is below 2"#;
+    let result = parse(&[NETWORK, CREDS, line].join("\n"));
+    assert!(matches!(result, Err(ParserError::ParseError(_))));
+}
+
+#[test]
+fn histogram_quantile_estimate_ok() {
+    let buckets = [(0.5, 30.0), (0.1, 10.0), (f64::INFINITY, 40.0), (1.0, 40.0)];
+    let estimate = |quantile| metrics::histogram_quantile(quantile, &buckets).unwrap();
+    assert!((estimate(0.5) - 0.3).abs() < 1e-9);
+    assert!((estimate(0.99) - 0.98).abs() < 1e-9);
+    // in the `+Inf` bucket
+    let buckets = [(1.0, 10.0), (f64::INFINITY, 20.0)];
+    assert_eq!(metrics::histogram_quantile(0.9, &buckets), Some(1.0));
+    assert_eq!(
+        metrics::histogram_quantile(1.5, &buckets),
+        Some(f64::INFINITY)
+    );
+}
+
+#[test]
+fn histogram_quantile_estimate_without_samples_ok() {
+    assert_eq!(metrics::histogram_quantile(0.5, &[(1.0, 10.0)]), None);
+    assert_eq!(
+        metrics::histogram_quantile(0.5, &[(1.0, 10.0), (2.0, 20.0)]),
+        None
+    );
+    assert_eq!(
+        metrics::histogram_quantile(0.5, &[(1.0, 0.0), (f64::INFINITY, 0.0)]),
+        None
+    );
+}
+
+#[test]
+fn para_finalized_height_parse_ok() {
+    let line: &str = r#"alice: parachain 100 finalized height is at least 10 within 200 seconds"#;
+    let data = r#"{
+        "description": null,
+        "network": "./a.toml",
+        "creds": "config",
+        "assertions": [
+            {
+                "original_line": "alice: parachain 100 finalized height is at least 10 within 200 seconds",
+                "parsed": {
+                    "fn": "ParaFinalizedHeight",
+                    "args": {
+                        "node_name": "alice",
+                        "para_id": 100,
+                        "op": "IsAtLeast",
+                        "target_value": 10,
+                        "timeout": 200
+                    }
+                }
+            }
+        ]
+    }"#;
+    let t: TestDefinition = serde_json::from_str(data).unwrap();
+
+    let result = parse(&[NETWORK, CREDS, line].join("\n")).unwrap();
+    assert_eq!(result, t);
+}
+
+#[test]
+fn para_block_production_parse_ok() {
+    let line: &str = r#"alice: parachain 100 produces at least 5 blocks within 60 seconds"#;
+    let data = r#"{
+        "description": null,
+        "network": "./a.toml",
+        "creds": "config",
+        "assertions": [
+            {
+                "original_line": "alice: parachain 100 produces at least 5 blocks within 60 seconds",
+                "parsed": {
+                    "fn": "ParaBlockProduction",
+                    "args": {
+                        "node_name": "alice",
+                        "para_id": 100,
+                        "op": "IsAtLeast",
+                        "target_value": 5,
+                        "timeout": 60
+                    }
+                }
+            }
+        ]
+    }"#;
+    let t: TestDefinition = serde_json::from_str(data).unwrap();
+
+    let result = parse(&[NETWORK, CREDS, line].join("\n")).unwrap();
+    assert_eq!(result, t);
+}
+
+#[test]
+fn para_block_production_exact_count_parse_ok() {
+    let line: &str = r#"alice: parachain 100 produces 1 block within 12 seconds"#;
+    let result = parse(&[NETWORK, CREDS, line].join("\n")).unwrap();
+
+    let AssertionKind::ParaBlockProduction {
+        op, target_value, ..
+    } = &assertion(&result.assertions[0]).parsed
+    else {
+        panic!("unexpected assertion {:?}", result.assertions[0]);
+    };
+    assert_eq!(*op, ast::Operator::Equal);
+    assert_eq!(
+        *target_value,
+        ast::TargetValue::Single(ast::NumericValue::Integer(1))
+    );
+}
+
+#[test]
+fn para_block_production_without_within_parse_err() {
+    let line: &str = r#"alice: parachain 100 produces 5 blocks"#;
+    let result = parse(&[NETWORK, CREDS, line].join("\n"));
+    assert!(result.is_err());
+}
+
+#[test]
+fn para_upgrade_expecting_spec_version_parse_ok() {
+    let line: &str = r#"alice: parachain 100 perform upgrade with https://example.com/runtimes/v1002.compact.wasm expecting spec_version 1002 within 200 seconds"#;
+    let result = parse(&[NETWORK, CREDS, line].join("\n")).unwrap();
+
+    let AssertionKind::ParaRuntimeUpgrade {
+        file_or_uri,
+        expected_spec_version,
+        timeout,
+        ..
+    } = &assertion(&result.assertions[0]).parsed
+    else {
+        panic!("unexpected assertion {:?}", result.assertions[0]);
+    };
+    assert_eq!(
+        file_or_uri,
+        "https://example.com/runtimes/v1002.compact.wasm"
+    );
+    assert_eq!(*expected_spec_version, Some(1002));
+    assert_eq!(*timeout, Some(Duration::from_secs(200)));
+}
+
+#[test]
+fn para_upgrade_invalid_runtime_parse_err() {
+    for runtime in [
+        "./some.wasm.compact",
+        "https:///runtime.wasm",
+        "http://-bad-.io/x",
+    ] {
+        let line = format!("alice: parachain 100 perform upgrade with {runtime}");
+        let result = parse(&[NETWORK, CREDS, &line].join("\n"));
+        assert!(
+            matches!(result, Err(ParserError::ParseError(_))),
+            "{runtime} should be rejected"
+        );
+    }
+}
+
+#[test]
+fn para_runtime_spec_version_parse_ok() {
+    let line: &str = r#"alice: parachain 100 runtime spec_version is 1002 within 60 seconds"#;
+    let data = r#"{
+        "description": null,
+        "network": "./a.toml",
+        "creds": "config",
+        "assertions": [
+            {
+                "original_line": "alice: parachain 100 runtime spec_version is 1002 within 60 seconds",
+                "parsed": {
+                    "fn": "ParaRuntimeSpecVersion",
+                    "args": {
+                        "node_name": "alice",
+                        "para_id": 100,
+                        "op": "Equal",
+                        "target_value": 1002,
+                        "timeout": 60
+                    }
+                }
+            }
+        ]
+    }"#;
+    let t: TestDefinition = serde_json::from_str(data).unwrap();
+
+    let result = parse(&[NETWORK, CREDS, line].join("\n")).unwrap();
+    assert_eq!(result, t);
+}
+
+#[test]
+fn para_runtime_spec_version_target_parse_err() {
+    for line in [
+        "alice: parachain 100 runtime spec_version is 10.5 within 60 seconds",
+        "alice: parachain 100 runtime spec_version is -1 within 60 seconds",
+        "alice: parachain 100 runtime spec_version is at least 4294967296",
+    ] {
+        let result = parse(&[NETWORK, CREDS, line].join("\n"));
+        assert!(
+            matches!(result, Err(ParserError::ParseError(msg)) if msg.contains("spec_version"))
+        );
+    }
+}
+
+#[test]
+fn submit_extrinsic_parse_ok() {
+    let line: &str = r#"alice: submit extrinsic balances.transferKeepAlive(//Bob, 1000) signed by //Alice and wait for finalization within 30 seconds"#;
+    let data = r#"{
+        "description": null,
+        "network": "./a.toml",
+        "creds": "config",
+        "assertions": [
+            {
+                "original_line": "alice: submit extrinsic balances.transferKeepAlive(//Bob, 1000) signed by //Alice and wait for finalization within 30 seconds",
+                "parsed": {
+                    "fn": "SubmitExtrinsic",
+                    "args": {
+                        "node_name": "alice",
+                        "pallet": "balances",
+                        "call": "transferKeepAlive",
+                        "args": [
+                            { "Account": "//Bob" },
+                            { "Number": 1000 }
+                        ],
+                        "signer": "//Alice",
+                        "wait_for": "Finalization",
+                        "timeout": 30
+                    }
+                }
+            }
+        ]
+    }"#;
+    let t: TestDefinition = serde_json::from_str(data).unwrap();
+
+    let result = parse(&[NETWORK, CREDS, line].join("\n")).unwrap();
+    assert_eq!(result, t);
+}
+
+#[test]
+fn submit_extrinsic_typed_args_parse_ok() {
+    let line: &str = r#"bob: submit extrinsic system.remark_with_event(0x1234, [true, "memo", -1.5], []) signed by //Bob//stash"#;
+    let result = parse(&[NETWORK, CREDS, line].join("\n")).unwrap();
+
+    let AssertionKind::SubmitExtrinsic {
+        args,
+        signer,
+        wait_for,
+        timeout,
+        ..
+    } = &assertion(&result.assertions[0]).parsed
+    else {
+        panic!("unexpected assertion {:?}", result.assertions[0]);
+    };
+    assert_eq!(
+        args,
+        &vec![
+            ast::ArgValue::Hex("0x1234".into()),
+            ast::ArgValue::List(vec![
+                ast::ArgValue::Bool(true),
+                ast::ArgValue::Text("memo".into()),
+                ast::ArgValue::Number(ast::NumericValue::Float(-1.5)),
+            ]),
+            ast::ArgValue::List(vec![]),
+        ]
+    );
+    assert_eq!(signer, "//Bob//stash");
+    assert_eq!(*wait_for, None);
+    assert_eq!(*timeout, None);
+}
+
+#[test]
+fn submit_extrinsic_in_parallel_block_parse_err() {
+    let lines = r#"parallel {
+        alice: submit extrinsic balances.transferKeepAlive(//Bob, 1000) signed by //Alice
+        bob: is up
+    }"#;
+    let result = parse(&[NETWORK, CREDS, lines].join("\n"));
+    assert!(matches!(result, Err(ParserError::InvalidParallelBlock(_))));
+}
+
+#[test]
+fn storage_query_parse_ok() {
+    let line: &str =
+        r#"alice: storage System.Account(//Bob).data.free is at least 100 within 30 seconds"#;
+    let data = r#"{
+        "description": null,
+        "network": "./a.toml",
+        "creds": "config",
+        "assertions": [
+            {
+                "original_line": "alice: storage System.Account(//Bob).data.free is at least 100 within 30 seconds",
+                "parsed": {
+                    "fn": "StorageQuery",
+                    "args": {
+                        "node_name": "alice",
+                        "pallet": "System",
+                        "item": "Account",
+                        "keys": [{ "Account": "//Bob" }],
+                        "path": ["data", "free"],
+                        "op": "IsAtLeast",
+                        "target_value": 100,
+                        "timeout": 30
+                    }
+                }
+            }
+        ]
+    }"#;
+    let t: TestDefinition = serde_json::from_str(data).unwrap();
+
+    let result = parse(&[NETWORK, CREDS, line].join("\n")).unwrap();
+    assert_eq!(result, t);
+}
+
+#[test]
+fn storage_query_without_keys_parse_ok() {
+    let line: &str = r#"alice: storage Balances.TotalIssuance is above 0
+    bob: storage Paras.Heads(100)[0] is 1"#;
+    let result = parse(&[NETWORK, CREDS, line].join("\n")).unwrap();
+
+    let storage = |step: &Step| match &assertion(step).parsed {
+        AssertionKind::StorageQuery {
+            pallet,
+            item,
+            keys,
+            path,
+            ..
+        } => (pallet.clone(), item.clone(), keys.clone(), path.clone()),
+        other => panic!("unexpected assertion {other:?}"),
+    };
+    assert_eq!(
+        storage(&result.assertions[0]),
+        ("Balances".into(), "TotalIssuance".into(), vec![], vec![])
+    );
+    assert_eq!(
+        storage(&result.assertions[1]),
+        (
+            "Paras".into(),
+            "Heads".into(),
+            vec![ast::ArgValue::Number(ast::NumericValue::Integer(100))],
+            vec![ast::PathSegment::Index(0)]
+        )
+    );
+}
+
+#[test]
+fn rpc_parse_ok() {
+    let line: &str = r#"alice: rpc system_health returns .peers is at least 3 within 10 seconds"#;
+    let data = r#"{
+        "description": null,
+        "network": "./a.toml",
+        "creds": "config",
+        "assertions": [
+            {
+                "original_line": "alice: rpc system_health returns .peers is at least 3 within 10 seconds",
+                "parsed": {
+                    "fn": "Rpc",
+                    "args": {
+                        "node_name": "alice",
+                        "method": "system_health",
+                        "params": [],
+                        "path": ["peers"],
+                        "op": "IsAtLeast",
+                        "target_value": 3,
+                        "timeout": 10
+                    }
+                }
+            }
+        ]
+    }"#;
+    let t: TestDefinition = serde_json::from_str(data).unwrap();
+
+    let result = parse(&[NETWORK, CREDS, line].join("\n")).unwrap();
+    assert_eq!(result, t);
+}
+
+#[test]
+fn report_u64_target_parse_ok() {
+    let line = "alice: reports some_counter is at most 18446744073709551615";
+    let result = parse(&[NETWORK, CREDS, line].join("\n")).unwrap();
+    assert!(matches!(
+        &assertion(&result.assertions[0]).parsed,
+        AssertionKind::Report {
+            target_value: ast::TargetValue::Single(ast::NumericValue::Float(value)),
+            ..
+        } if *value == u64::MAX as f64
+    ));
+}
+
+#[test]
+fn report_infinite_target_parse_err() {
+    let line = "alice: reports some_counter is 1e400";
+    let result = parse(&[NETWORK, CREDS, line].join("\n"));
+    assert!(matches!(result, Err(ParserError::ParseError(_))));
+}
+
+#[test]
+fn rpc_bool_and_string_targets_parse_ok() {
+    let lines = r#"
+    alice: rpc system_health returns .isSyncing == false
+    alice: rpc chain_getBlockHash [0] returns != "0x00"
+    alice: rpc state_call ["Core_version", {"at": null, "n": [1, 2.5]}] returns .result[0].name is "polkadot"
+    "#;
+    let result = parse(&[NETWORK, CREDS, lines].join("\n")).unwrap();
+
+    let rpc = |step: &Step| match &assertion(step).parsed {
+        AssertionKind::Rpc {
+            params,
+            path,
+            op,
+            target_value,
+            ..
+        } => (params.clone(), path.clone(), *op, target_value.clone()),
+        other => panic!("unexpected assertion {other:?}"),
+    };
+    assert_eq!(
+        rpc(&result.assertions[0]),
+        (
+            vec![],
+            vec![ast::PathSegment::Field("isSyncing".into())],
+            ast::Operator::Equal,
+            ast::TargetValue::Bool(false)
+        )
+    );
+    assert_eq!(
+        rpc(&result.assertions[1]),
+        (
+            vec![serde_json::json!(0)],
+            vec![],
+            ast::Operator::NotEqual,
+            ast::TargetValue::Text("0x00".into())
+        )
+    );
+    assert_eq!(
+        rpc(&result.assertions[2]),
+        (
+            vec![
+                serde_json::json!("Core_version"),
+                serde_json::json!({"at": null, "n": [1, 2.5]})
+            ],
+            vec![
+                ast::PathSegment::Field("result".into()),
+                ast::PathSegment::Index(0),
+                ast::PathSegment::Field("name".into())
+            ],
+            ast::Operator::Equal,
+            ast::TargetValue::Text("polkadot".into())
+        )
+    );
+}
+
+#[test]
+fn rpc_ordering_on_bool_parse_err() {
+    let line: &str = r#"alice: rpc system_health returns .isSyncing is at least true"#;
+    let result = parse(&[NETWORK, CREDS, line].join("\n"));
+    assert!(result.is_err());
+}
+
+#[test]
+fn report_bool_target_parse_err() {
+    let line: &str = r#"alice: reports node_roles is true"#;
+    let result = parse(&[NETWORK, CREDS, line].join("\n"));
+    assert!(result.is_err());
+}
+
+#[test]
+fn disconnect_parse_ok() {
+    let line: &str = r#"alice: disconnect from bob,charlie for 30 seconds"#;
+    let data = r#"{
+        "description": null,
+        "network": "./a.toml",
+        "creds": "config",
+        "assertions": [
+            {
+                "original_line": "alice: disconnect from bob,charlie for 30 seconds",
+                "parsed": {
+                    "fn": "Disconnect",
+                    "args": {
+                        "node_name": "alice",
+                        "peers": { "nodes": ["bob", "charlie"] },
+                        "duration": 30
+                    }
+                }
+            }
+        ]
+    }"#;
+    let t: TestDefinition = serde_json::from_str(data).unwrap();
+
+    let result = parse(&[NETWORK, CREDS, line].join("\n")).unwrap();
+    assert_eq!(result, t);
+}
+
+#[test]
+fn chaos_commands_parse_ok() {
+    let lines = r#"
+    isolate charlie for 30 seconds
+    add latency 200 ms to dave
+    drop 10% packets on all validators for 1 minute
+    "#;
+    let result = parse(&[NETWORK, CREDS, lines].join("\n")).unwrap();
+
+    let parsed: Vec<_> = result
+        .assertions
+        .iter()
+        .map(|step| &assertion(step).parsed)
+        .collect();
+    assert_eq!(
+        parsed,
+        vec![
+            &AssertionKind::Isolate {
+                node_name: NodeSelector::Name("charlie".into()),
+                duration: Some(Duration::from_secs(30)),
+            },
+            &AssertionKind::AddLatency {
+                node_name: NodeSelector::Name("dave".into()),
+                latency: Duration::from_millis(200),
+                duration: None,
+            },
+            &AssertionKind::PacketLoss {
+                node_name: NodeSelector::Group {
+                    group: "validators".into()
+                },
+                loss_percent: ast::NumericValue::Integer(10),
+                duration: Some(Duration::from_secs(60)),
+            },
+        ]
+    );
+    assert!(parsed.iter().all(|kind| kind.is_command()));
+
+    let json = serde_json::to_value(parsed[1]).unwrap();
+    assert_eq!(json["args"]["latency"], serde_json::json!(0.2));
+}
+
+#[test]
+fn packet_loss_over_hundred_percent_parse_err() {
+    let line: &str = r#"drop 150% packets on eve"#;
+    let result = parse(&[NETWORK, CREDS, line].join("\n"));
+    assert!(matches!(result, Err(ParserError::ParseError(_))));
+}
+
+#[test]
+fn add_and_remove_node_parse_ok() {
+    let lines = r#"
+    add node ferdie from template bob with args "--sync=warp --pruning archive"
+    ferdie: is up within 60 seconds
+    remove node ferdie
+    "#;
+    let data = r#"{
+        "description": null,
+        "network": "./a.toml",
+        "creds": "config",
+        "assertions": [
+            {
+                "original_line": "add node ferdie from template bob with args \"--sync=warp --pruning archive\"",
+                "parsed": {
+                    "fn": "AddNode",
+                    "args": {
+                        "node_name": "ferdie",
+                        "template": "bob",
+                        "args": ["--sync=warp", "--pruning", "archive"]
+                    }
+                }
+            },
+            {
+                "original_line": "ferdie: is up within 60 seconds",
+                "parsed": {
+                    "fn": "IsUp",
+                    "args": {
+                        "node_name": "ferdie",
+                        "timeout": 60
+                    }
+                }
+            },
+            {
+                "original_line": "remove node ferdie",
+                "parsed": {
+                    "fn": "RemoveNode",
+                    "args": {
+                        "node_name": "ferdie"
+                    }
+                }
+            }
+        ]
+    }"#;
+    let t: TestDefinition = serde_json::from_str(data).unwrap();
+
+    let result = parse(&[NETWORK, CREDS, lines].join("\n")).unwrap();
+    assert_eq!(result, t);
+}
+
+#[test]
+fn removed_node_reference_parse_err() {
+    let lines = r#"
+    remove node bob
+    Section: after removal
+    alice,bob: is up
+    "#;
+    let result = parse(&[NETWORK, CREDS, lines].join("\n"));
+    assert!(
+        matches!(result, Err(ParserError::UnknownNode(ref node, ref line)) if node == "bob" && line == "alice,bob: is up")
+    );
+}
+
+#[test]
+fn readded_node_reference_parse_ok() {
+    let lines = r#"
+    remove node bob
+    alice: disconnect from charlie
+    add node bob from template alice
+    alice: disconnect from bob
+    "#;
+    assert!(parse(&[NETWORK, CREDS, lines].join("\n")).is_ok());
+}
+
+#[test]
+fn add_node_errors_parse_err() {
+    let duplicate = r#"
+    add node ferdie from template bob
+    add node ferdie from template bob
+    "#;
+    let result = parse(&[NETWORK, CREDS, duplicate].join("\n"));
+    assert!(matches!(result, Err(ParserError::DuplicateNode(..))));
+
+    let removed_template = r#"
+    remove node bob
+    add node ferdie from template bob
+    "#;
+    let result = parse(&[NETWORK, CREDS, removed_template].join("\n"));
+    assert!(matches!(result, Err(ParserError::UnknownNode(..))));
+}
+
+#[test]
+fn node_reference_before_add_parse_err() {
+    let lines = r#"
+    ferdie: is up
+    add node ferdie from template bob
+    "#;
+    let result = parse(&[NETWORK, CREDS, lines].join("\n"));
+    assert!(
+        matches!(result, Err(ParserError::UnknownNode(ref node, ref line)) if node == "ferdie" && line == "ferdie: is up")
+    );
+
+    let template_added_later = r#"
+    add node ferdie from template eve
+    add node eve from template bob
+    "#;
+    let result = parse(&[NETWORK, CREDS, template_added_later].join("\n"));
+    assert!(matches!(result, Err(ParserError::UnknownNode(ref node, _)) if node == "eve"));
+}
+
+#[test]
+fn add_node_self_template_parse_err() {
+    let line = "add node ferdie from template ferdie";
+    let result = parse(&[NETWORK, CREDS, line].join("\n"));
+    assert!(matches!(result, Err(ParserError::ParseError(msg)) if msg.contains("own template")));
+}
+
+#[test]
+fn upgrade_node_binary_parse_ok() {
+    let line: &str = r#"alice: upgrade binary to ./polkadot-new"#;
+    let data = r#"{
+        "description": null,
+        "network": "./a.toml",
+        "creds": "config",
+        "assertions": [
+            {
+                "original_line": "alice: upgrade binary to ./polkadot-new",
+                "parsed": {
+                    "fn": "UpgradeNode",
+                    "args": {
+                        "node_name": "alice",
+                        "artifact": { "Binary": "./polkadot-new" },
+                        "args": [],
+                        "after": null
+                    }
+                }
+            }
+        ]
+    }"#;
+    let t: TestDefinition = serde_json::from_str(data).unwrap();
+
+    let result = parse(&[NETWORK, CREDS, line].join("\n")).unwrap();
+    assert_eq!(result, t);
+}
+
+#[test]
+fn upgrade_node_image_parse_ok() {
+    let line: &str = r#"validator-*: upgrade image to docker.io/parity/polkadot:latest with args "--db-cache 512" after 10 seconds"#;
+    let result = parse(&[NETWORK, CREDS, line].join("\n")).unwrap();
+
+    assert_eq!(
+        assertion(&result.assertions[0]).parsed,
+        AssertionKind::UpgradeNode {
+            node_name: NodeSelector::Glob {
+                glob: "validator-*".into()
+            },
+            artifact: ast::UpgradeArtifact::Image("docker.io/parity/polkadot:latest".into()),
+            args: vec!["--db-cache".into(), "512".into()],
+            after: Some(Duration::from_secs(10)),
+        }
+    );
+}
+
+#[test]
+fn backchannel_exists_parse_ok() {
+    let line: &str = r#"backchannel key "dispute-candidate" exists within 60 seconds"#;
+    let data = r#"{
+        "description": null,
+        "network": "./a.toml",
+        "creds": "config",
+        "assertions": [
+            {
+                "original_line": "backchannel key \"dispute-candidate\" exists within 60 seconds",
+                "parsed": {
+                    "fn": "Backchannel",
+                    "args": {
+                        "key": "dispute-candidate",
+                        "timeout": 60
+                    }
+                }
+            }
+        ]
+    }"#;
+    let t: TestDefinition = serde_json::from_str(data).unwrap();
+
+    let result = parse(&[NETWORK, CREDS, line].join("\n")).unwrap();
+    assert_eq!(result, t);
+}
+
+#[test]
+fn backchannel_comparison_parse_ok() {
+    let lines = r#"
+    backchannel key "x" is "value"
+    backchannel key "votes" is at least 3 within 30 seconds
+    "#;
+    let result = parse(&[NETWORK, CREDS, lines].join("\n")).unwrap();
+
+    let json = serde_json::to_value(&result.assertions).unwrap();
+    assert_eq!(
+        json[0]["parsed"]["args"],
+        serde_json::json!({ "key": "x", "op": "Equal", "target_value": "value", "timeout": null })
+    );
+    assert_eq!(
+        json[1]["parsed"]["args"],
+        serde_json::json!({ "key": "votes", "op": "IsAtLeast", "target_value": 3, "timeout": 30 })
+    );
+    let t: Vec<Step> = serde_json::from_value(json).unwrap();
+    assert_eq!(t, result.assertions);
+}
+
 #[test]
 fn is_up_parse_err() {
     let result = parse("alice: is upp");
@@ -54,15 +54,11 @@ export async function test(
       : "kubernetes";
 
   const configBasePath = path.dirname(testFile);
-  const env = new Environment(new RelativeLoader([configBasePath]));
-  const templateContent = fs.readFileSync(testFile).toString();
-  const content = env.renderString(templateContent, process.env);
-
   const testName = getTestNameFromFileName(testFile);
 
   let testDef: TestDefinition;
   try {
-    testDef = JSON.parse(parser.parse_to_json(content));
+    testDef = parseTestFile(testFile, parseVars(opts.var || []));
   } catch (e) {
     console.log(`\n ${decorators.red("Error:")} \t ${decorators.bright(e)}\n`);
     process.exit(1);
@@ -82,6 +78,50 @@ export async function test(
   );
 }
 
+/**
+ * Render the templates of the test file (with the environment variables) and parse it,
+ * resolving the `${VAR}` references with the `vars`, the `let` bindings and the environment.
+ * @param testFile
+ * @param vars - variables of the test file (e.g. from `--var KEY=VALUE`)
+ * @returns the test definition
+ */
+export function parseTestFile(
+  testFile: string,
+  vars: Record<string, string> = {},
+): TestDefinition {
+  const configBasePath = path.dirname(testFile);
+  const env = new Environment(new RelativeLoader([configBasePath]));
+  const templateContent = fs.readFileSync(testFile).toString();
+  const content = env.renderString(templateContent, process.env);
+
+  // pass the test file to resolve the `Include:` paths relative to it, and the
+  // environment since it isn't available to the (wasm) parser
+  return JSON.parse(
+    parser.parse_to_json(
+      content,
+      JSON.stringify(vars),
+      testFile,
+      undefined,
+      undefined,
+      JSON.stringify(process.env),
+    ),
+  );
+}
+
+function parseVars(assignments: string[]): Record<string, string> {
+  const vars: Record<string, string> = {};
+  for (const assignment of assignments) {
+    const idx = assignment.indexOf("=");
+    if (idx === -1) {
+      throw new Error(
+        `invalid KEY=VALUE: no \`=\` found in \`${assignment}\``,
+      );
+    }
+    vars[assignment.slice(0, idx)] = assignment.slice(idx + 1);
+  }
+  return vars;
+}
+
 function getTestNameFromFileName(testFile: string): string {
   const fileWithOutExt = testFile.split(".")[0];
   const fileName: string = fileWithOutExt.split("/").pop() || "";
@@ -123,6 +123,15 @@ program
     "[runningNetworkSpec]",
     "Path to the network spec json, for using a running network for running the test",
   )
+  .addOption(
+    new Option(
+      "--var <assignment>",
+      "Variable to use in the test file, as KEY=VALUE (can be repeated)",
+    ).argParser((value: string, previous: string[] = []) => [
+      ...previous,
+      value,
+    ]),
+  )
   .action(asyncAction(test));
 
 program
@@ -0,0 +1,47 @@
+import { mkdirSync, rmSync, writeFileSync } from "fs";
+import path from "path";
+
+import { expect } from "chai";
+import { parseTestFile } from "../actions/test";
+
+describe("Tests on module 'test';", () => {
+  const tmpDir = path.join(__dirname, "tmp_tests");
+  const testFile = path.join(tmpDir, "0001-vars.zndsl");
+
+  before(function () {
+    mkdirSync(tmpDir, { recursive: true });
+    process.env.ZOMBIE_SPEC_NODE = "alice";
+    process.env.ZOMBIE_SPEC_PARA = "1000";
+  });
+
+  after(function () {
+    rmSync(tmpDir, { recursive: true, force: true });
+    delete process.env.ZOMBIE_SPEC_NODE;
+    delete process.env.ZOMBIE_SPEC_PARA;
+  });
+
+  it("resolves the variables after rendering the templates", () => {
+    writeFileSync(
+      testFile,
+      [
+        "Network: ./a.toml",
+        "Creds: config",
+        "let PARA = 2000",
+        "{{ZOMBIE_SPEC_NODE}}: parachain ${PARA} is registered",
+        "bob: parachain ${ZOMBIE_SPEC_PARA} is registered",
+        "bob: reports block height is at least ${THRESHOLD}",
+      ].join("\n"),
+    );
+
+    const testDef = parseTestFile(testFile, { THRESHOLD: "10" });
+    const lines = testDef.assertions.map(
+      (assertion: any) => assertion.original_line,
+    );
+
+    expect(lines).to.deep.equal([
+      "alice: parachain 2000 is registered",
+      "bob: parachain 1000 is registered",
+      "bob: reports block height is at least 10",
+    ]);
+  });
+});